@@ -16,6 +16,34 @@ typedef enum {
   AVRO_CODEC_SNAPPY,
 } AvroCodec;
 
+/*
+ * Representation of enum values in pickled values.
+ */
+typedef enum {
+  /*
+   * Enums are the str of their symbol.
+   */
+  AVRO_ENUM_MODE_SYMBOL,
+  /*
+   * Enums are the int index of their symbol.
+   */
+  AVRO_ENUM_MODE_INDEX,
+} AvroEnumMode;
+
+/*
+ * Representation of records in pickled values read from avro data.
+ */
+typedef enum {
+  /*
+   * Records are dicts, with their fields in the order of the schema.
+   */
+  AVRO_RECORD_MODE_DICT,
+  /*
+   * Records are tuples of their field values, in the order of the schema.
+   */
+  AVRO_RECORD_MODE_TUPLE,
+} AvroRecordMode;
+
 /*
  * C-style error codes
  */
@@ -26,6 +54,8 @@ enum AvroErrorCode {
 };
 typedef uint32_t AvroErrorCode;
 
+typedef struct AvroDiff AvroDiff;
+
 typedef struct AvroReader AvroReader;
 
 typedef struct AvroRecord AvroRecord;
@@ -36,6 +66,46 @@ typedef struct AvroValue AvroValue;
 
 typedef struct AvroWriter AvroWriter;
 
+#ifndef ARROW_C_DATA_INTERFACE
+#define ARROW_C_DATA_INTERFACE
+
+#define ARROW_FLAG_DICTIONARY_ORDERED 1
+#define ARROW_FLAG_NULLABLE 2
+#define ARROW_FLAG_MAP_KEYS_SORTED 4
+
+/*
+ * Type of an Arrow array, as described by the Arrow C Data Interface.
+ */
+struct ArrowSchema {
+  const char *format;
+  const char *name;
+  const char *metadata;
+  int64_t flags;
+  int64_t n_children;
+  struct ArrowSchema **children;
+  struct ArrowSchema *dictionary;
+  void (*release)(struct ArrowSchema*);
+  void *private_data;
+};
+
+/*
+ * Data of an Arrow array, as described by the Arrow C Data Interface.
+ */
+struct ArrowArray {
+  int64_t length;
+  int64_t null_count;
+  int64_t offset;
+  int64_t n_buffers;
+  int64_t n_children;
+  const void **buffers;
+  struct ArrowArray **children;
+  struct ArrowArray *dictionary;
+  void (*release)(struct ArrowArray*);
+  void *private_data;
+};
+
+#endif  /* ARROW_C_DATA_INTERFACE */
+
 /*
  * Represents a byte array.
  */
@@ -45,6 +115,77 @@ typedef struct {
   bool owned;
 } AvroByteArray;
 
+/*
+ * Options controlling the conversion of pickled values to and from avro values, as given across
+ * the FFI boundary: flags are 0 or 1, and modes are values of `AvroEnumMode` and
+ * `AvroRecordMode`.
+ */
+typedef struct {
+  /*
+   * Only accept the Python type closest to each schema: ints for floats and doubles, sets and
+   * tuples for arrays, bytes keys for records and enums in the other representation than
+   * `enum_mode` are rejected.
+   */
+  uint8_t strict;
+  /*
+   * Accept bytes for strings and str for bytes.
+   */
+  uint8_t str_bytes_interchange;
+  /*
+   * Convert map keys which are not str (bytes, ints, floats, bools and None) to strings,
+   * instead of rejecting them.
+   */
+  uint8_t stringify_map_keys;
+  /*
+   * Representation of enums, both accepted and produced, an `AvroEnumMode`.
+   */
+  int32_t enum_mode;
+  /*
+   * Convert fields missing from a dict to null when their schema is a union with null, even
+   * if they have no default.
+   */
+  uint8_t missing_nullable_as_null;
+  /*
+   * Reject dict keys which are not fields of the record, instead of ignoring them.
+   */
+  uint8_t reject_extra_keys;
+  /*
+   * Representation of records in pickled values read from avro data, an `AvroRecordMode`.
+   */
+  int32_t record_mode;
+  /*
+   * Protocol of the pickled values read from avro data, 2 or 3.
+   */
+  uint8_t pickle_protocol;
+} AvroConversionOptions;
+
+/*
+ * Options controlling the generation of random avro values.
+ */
+typedef struct {
+  /*
+   * Maximum number of items in an array.
+   */
+  uintptr_t max_array_len;
+  /*
+   * Maximum number of entries in a map.
+   */
+  uintptr_t max_map_len;
+  /*
+   * Maximum length of strings and bytes.
+   */
+  uintptr_t max_string_len;
+  /*
+   * Maximum nesting of arrays, maps, unions and records. Past it, arrays and maps are
+   * empty and unions are null.
+   */
+  uintptr_t max_depth;
+  /*
+   * Probability (between 0 and 1) for a union to be null.
+   */
+  double null_probability;
+} AvroRandomOptions;
+
 /*
  * Represents a string.
  */
@@ -54,8 +195,59 @@ typedef struct {
   bool owned;
 } AvroStr;
 
+/*
+ * Callbacks fired while walking an avro value.
+ *
+ * Every callback is optional and receives `user_data` as its first argument. Strings and
+ * bytes are borrowed and only valid during the callback. Returning a non-zero value from a
+ * callback stops the walk.
+ */
+typedef struct {
+  void *user_data;
+  int (*on_null)(void*);
+  int (*on_boolean)(void*, bool);
+  int (*on_int)(void*, int32_t);
+  int (*on_long)(void*, int64_t);
+  int (*on_float)(void*, float);
+  int (*on_double)(void*, double);
+  int (*on_bytes)(void*, AvroByteArray);
+  int (*on_string)(void*, AvroStr);
+  int (*on_fixed)(void*, AvroByteArray);
+  /*
+   * Called with the index and the symbol of the enum.
+   */
+  int (*on_enum)(void*, int, AvroStr);
+  /*
+   * Called with the branch index of a union, before the branch value.
+   */
+  int (*on_union)(void*, uintptr_t);
+  int (*on_array_start)(void*);
+  int (*on_array_end)(void*);
+  int (*on_map_start)(void*);
+  /*
+   * Called with the key of a map entry, before its value.
+   */
+  int (*on_map_key)(void*, AvroStr);
+  int (*on_map_end)(void*);
+  int (*on_record_start)(void*);
+  /*
+   * Called with the name of a record field, before its value.
+   */
+  int (*on_field)(void*, AvroStr);
+  int (*on_record_end)(void*);
+} AvroVisitor;
+
 void avro_array_append(AvroValue *array, AvroValue *value);
 
+/*
+ * Compare two avro serialized datums following the avro sort order of a schema, without
+ * decoding them.
+ *
+ * Return a negative number, zero or a positive number if the first datum is respectively
+ * lower than, equal to or greater than the second one.
+ */
+int avro_binary_compare(const AvroSchema *schema, const AvroByteArray *a, const AvroByteArray *b);
+
 /*
  * Frees a avro byte array.
  *
@@ -73,6 +265,54 @@ void avro_byte_array_free(AvroByteArray *a);
  */
 AvroByteArray avro_byte_array_from_c_array(const unsigned char *a, uintptr_t len);
 
+/*
+ * Default options for the conversion of pickled values: lenient, matching the conversion of
+ * writers and readers created without options.
+ */
+AvroConversionOptions avro_conversion_options_default(void);
+
+/*
+ * Walk an avro serialized datum of a schema, firing the callbacks of a visitor. The datum
+ * is not decoded into an avro value.
+ *
+ * Return 0 if the whole datum was walked, or the non-zero code of the callback which
+ * stopped the walk.
+ *
+ * Blocks of arrays and maps with more items than bytes left in the datum are errors, so
+ * that malformed data cannot make the walk fire callbacks for items it does not hold.
+ */
+int avro_datum_visit(const AvroByteArray *buffer,
+                     const AvroSchema *schema,
+                     const AvroVisitor *visitor);
+
+/*
+ * Free a diff.
+ */
+void avro_diff_free(AvroDiff *diff);
+
+/*
+ * Number of changes in a diff.
+ */
+uintptr_t avro_diff_len(const AvroDiff *diff);
+
+/*
+ * New value of a change, borrowed from the diff. Null for a map entry removed by the new
+ * value.
+ */
+const AvroValue *avro_diff_new_value(const AvroDiff *diff, uintptr_t index);
+
+/*
+ * Old value of a change, borrowed from the diff. Null for a map entry added by the new
+ * value.
+ */
+const AvroValue *avro_diff_old_value(const AvroDiff *diff, uintptr_t index);
+
+/*
+ * Path of a change, such as `a.b` or `tags["env"]`, to be used with `avro_value_get_path`.
+ * The string must be freed with `avro_str_free`.
+ */
+AvroStr avro_diff_path(const AvroDiff *diff, uintptr_t index);
+
 /*
  * Clears the last error.
  */
@@ -103,6 +343,22 @@ AvroStr avro_err_get_last_message(void);
  */
 AvroByteArray avro_from_avro_datum(const AvroByteArray *buffer, const AvroSchema *schema);
 
+/*
+ * Read avro serialized data into a MessagePack-encoded value, converted with the given
+ * options. Default options are used if `options` is null.
+ */
+AvroByteArray avro_from_avro_datum_msgpack(const AvroByteArray *buffer,
+                                           const AvroSchema *schema,
+                                           const AvroConversionOptions *options);
+
+/*
+ * Read avro serialized data into a pickled value, converted with the given options.
+ * Default options are used if `options` is null.
+ */
+AvroByteArray avro_from_avro_datum_with_options(const AvroByteArray *buffer,
+                                                const AvroSchema *schema,
+                                                const AvroConversionOptions *options);
+
 /*
  * Initializes the library
  */
@@ -110,6 +366,17 @@ void avro_init(void);
 
 void avro_map_put(AvroValue *map, AvroStr key, AvroValue *value);
 
+/*
+ * Default options for the generation of random avro values.
+ */
+AvroRandomOptions avro_random_options_default(void);
+
+/*
+ * Export the type of the record batches read with `avro_reader_read_arrow` to `schema`,
+ * through the Arrow C Data Interface. The schema must be released by its consumer.
+ */
+void avro_reader_arrow_schema(AvroReader *reader, struct ArrowSchema *schema);
+
 /*
  * Free an avro reader. Does NOT free the buffer the reader reads from.
  */
@@ -120,6 +387,56 @@ void avro_reader_free(AvroReader *reader);
  */
 AvroReader *avro_reader_new(const AvroByteArray *buffer, const AvroSchema *schema);
 
+/*
+ * Create an avro reader given an avro byte array used as buffer, an avro schema (which may
+ * be null to use the schema of the data) and the options to convert the values read with
+ * `avro_reader_read_next`. Default options are used if `options` is null.
+ */
+AvroReader *avro_reader_new_with_options(const AvroByteArray *buffer,
+                                         const AvroSchema *schema,
+                                         const AvroConversionOptions *options);
+
+/*
+ * Read all the remaining values out of an avro reader, as a pickled list. As with
+ * `avro_reader_read_batch`, an error is reported by the next read if values were read
+ * before it.
+ */
+AvroByteArray avro_reader_read_all(AvroReader *reader);
+
+/*
+ * Read up to `batch_size` records out of an avro reader into an Arrow record batch,
+ * exported through the Arrow C Data Interface to `schema` and `array`, which must be
+ * released by their consumer. The schema of the data must be a record.
+ *
+ * Return the number of records read. Once all of them are read, 0 is returned and neither
+ * `schema` nor `array` is written. Errors are reported as by `avro_reader_read_batch`: the
+ * records read before an error are returned, and the error by the next read. This holds
+ * for records which cannot be converted to Arrow as well.
+ *
+ * avro-rs 0.4 does not parse logical types, so values of a logical type come out as their
+ * underlying type: e.g. `timestamp-millis` longs as `int64` rather than timestamps, and
+ * `decimal` bytes as `binary`.
+ */
+uintptr_t avro_reader_read_arrow(AvroReader *reader,
+                                 uintptr_t batch_size,
+                                 struct ArrowSchema *schema,
+                                 struct ArrowArray *array);
+
+/*
+ * Read up to `max_records` values out of an avro reader, as a pickled list. The list is
+ * empty once all the values are read.
+ *
+ * Reading stops at the first value which cannot be read: the values read before it are
+ * returned, and the error is reported by the next read.
+ */
+AvroByteArray avro_reader_read_batch(AvroReader *reader, uintptr_t max_records);
+
+/*
+ * Read up to `max_records` values out of an avro reader, as an array value. The array is
+ * empty once all the values are read. Errors are reported as by `avro_reader_read_batch`.
+ */
+AvroValue *avro_reader_read_batch2(AvroReader *reader, uintptr_t max_records);
+
 /*
  * Read the next chunk of data out of an avro reader.
  */
@@ -130,10 +447,32 @@ AvroByteArray avro_reader_read_next(AvroReader *reader);
  */
 AvroValue *avro_reader_read_next2(AvroReader *reader);
 
+/*
+ * Read the next value out of an avro reader, encoded as MessagePack. The byte array is
+ * empty once all the values are read.
+ */
+AvroByteArray avro_reader_read_next_msgpack(AvroReader *reader);
+
 void avro_record_free(AvroRecord *r);
 
+/*
+ * Get a field of a record under construction. The value is borrowed from the record.
+ */
+const AvroValue *avro_record_get(const AvroRecord *record, const AvroStr *field);
+
+/*
+ * Create an empty record for a record schema.
+ *
+ * The schema must outlive the record.
+ */
 AvroRecord *avro_record_new(const AvroSchema *schema);
 
+/*
+ * Set a field of a record.
+ *
+ * Fails if the field is not in the record schema or if the value does not match the schema
+ * of the field. The value is consumed in any case.
+ */
 void avro_record_put(AvroRecord *record, const AvroStr *field, AvroValue *value);
 
 AvroValue *avro_record_to_value(AvroRecord *record);
@@ -148,6 +487,18 @@ void avro_schema_free(AvroSchema *schema);
  */
 AvroSchema *avro_schema_from_json(const AvroStr *json);
 
+/*
+ * Get the schema of a field of a record schema. The schema is borrowed from the record
+ * schema.
+ */
+const AvroSchema *avro_schema_record_field_get(const AvroSchema *schema, const AvroStr *field);
+
+/*
+ * Get the position of a field in a record schema. Records read or created with the schema
+ * have their fields in the same order, to be accessed with `avro_value_record_get_by_index`.
+ */
+uintptr_t avro_schema_record_field_index(const AvroSchema *schema, const AvroStr *field);
+
 /*
  * Frees a avro str.
  *
@@ -170,8 +521,90 @@ AvroStr avro_str_from_c_str(const char *s);
  */
 AvroByteArray avro_to_avro_datum(const AvroSchema *schema, AvroValue *value);
 
+/*
+ * Write a single avro datum to a buffer and return the avro serialized data, with map
+ * entries sorted by key so that the same value always gives the same bytes. The value is
+ * not consumed.
+ */
+AvroByteArray avro_to_avro_datum_deterministic(const AvroSchema *schema, const AvroValue *value);
+
+/*
+ * Write a single avro datum to a buffer and return the avro serialized data, without taking
+ * ownership of the value. The bytes are the same as the ones of `avro_to_avro_datum`.
+ */
+AvroByteArray avro_to_avro_datum_ref(const AvroSchema *schema, const AvroValue *value);
+
+/*
+ * Create an array of boolean values, copied from a buffer of `len` items.
+ *
+ * Booleans are bytes: any non-zero byte is true, and true is copied out as 1.
+ */
+AvroValue *avro_value_array_from_bools(const uint8_t *data, uintptr_t len);
+
+/*
+ * Create an array of double values, copied from a buffer of `len` items.
+ */
+AvroValue *avro_value_array_from_doubles(const double *data, uintptr_t len);
+
+/*
+ * Create an array of float values, copied from a buffer of `len` items.
+ */
+AvroValue *avro_value_array_from_floats(const float *data, uintptr_t len);
+
+/*
+ * Create an array of int values, copied from a buffer of `len` items.
+ */
+AvroValue *avro_value_array_from_ints(const int32_t *data, uintptr_t len);
+
+/*
+ * Create an array of long values, copied from a buffer of `len` items.
+ */
+AvroValue *avro_value_array_from_longs(const int64_t *data, uintptr_t len);
+
+/*
+ * Number of items in an array.
+ */
+uintptr_t avro_value_array_len(const AvroValue *array);
+
 AvroValue *avro_value_array_new(uintptr_t capacity);
 
+/*
+ * Copy an array of boolean values to a buffer of `len` items, returning the number of items copied.
+ *
+ * Fails if the array is longer than the buffer or if an item is not a boolean.
+ *
+ * Booleans are bytes: any non-zero byte is true, and true is copied out as 1.
+ */
+uintptr_t avro_value_array_to_bools(const AvroValue *array, uint8_t *out, uintptr_t len);
+
+/*
+ * Copy an array of double values to a buffer of `len` items, returning the number of items copied.
+ *
+ * Fails if the array is longer than the buffer or if an item is not a double.
+ */
+uintptr_t avro_value_array_to_doubles(const AvroValue *array, double *out, uintptr_t len);
+
+/*
+ * Copy an array of float values to a buffer of `len` items, returning the number of items copied.
+ *
+ * Fails if the array is longer than the buffer or if an item is not a float.
+ */
+uintptr_t avro_value_array_to_floats(const AvroValue *array, float *out, uintptr_t len);
+
+/*
+ * Copy an array of int values to a buffer of `len` items, returning the number of items copied.
+ *
+ * Fails if the array is longer than the buffer or if an item is not a int.
+ */
+uintptr_t avro_value_array_to_ints(const AvroValue *array, int32_t *out, uintptr_t len);
+
+/*
+ * Copy an array of long values to a buffer of `len` items, returning the number of items copied.
+ *
+ * Fails if the array is longer than the buffer or if an item is not a long.
+ */
+uintptr_t avro_value_array_to_longs(const AvroValue *array, int64_t *out, uintptr_t len);
+
 bool avro_value_boolean_get(const AvroValue *value);
 
 AvroValue *avro_value_boolean_new(int32_t b);
@@ -180,24 +613,80 @@ AvroByteArray avro_value_bytes_get(const AvroValue *value);
 
 AvroValue *avro_value_bytes_new(AvroByteArray b);
 
+/*
+ * Compare two avro values following the avro sort order of a schema.
+ *
+ * Return a negative number, zero or a positive number if the first value is respectively
+ * lower than, equal to or greater than the second one.
+ */
+int avro_value_compare(const AvroSchema *schema, const AvroValue *a, const AvroValue *b);
+
+/*
+ * Create a complete record value for a record schema, out of the defaults of its fields.
+ *
+ * Nullable fields without a default are set to null. Fails, listing them, if some fields
+ * have no default and are not nullable.
+ */
+AvroValue *avro_value_default_for_schema(const AvroSchema *schema);
+
+/*
+ * Compute the differences between two values of a schema. Records, maps and non-null
+ * unions are compared item by item, any other value is compared as a whole.
+ *
+ * The values are not consumed. The diff must be freed with `avro_diff_free`.
+ */
+AvroDiff *avro_value_diff(const AvroSchema *schema, const AvroValue *old, const AvroValue *new_);
+
 double avro_value_double_get(const AvroValue *value);
 
 AvroValue *avro_value_double_new(double x);
 
+/*
+ * Number of bytes `avro_to_avro_datum` would produce for a value, without encoding it.
+ */
+uintptr_t avro_value_encoded_size(const AvroSchema *schema, const AvroValue *value);
+
+/*
+ * Number of bytes `avro_to_avro_datum` would produce for each of `n` values, written to
+ * `sizes`. Return the total number of bytes.
+ */
+uintptr_t avro_value_encoded_sizes(const AvroSchema *schema,
+                                   const AvroValue *const *values,
+                                   uintptr_t n,
+                                   uintptr_t *sizes);
+
 AvroStr avro_value_enum_get(const AvroValue *value);
 
 AvroValue *avro_value_enum_new(int value_index, AvroStr value_repr);
 
+/*
+ * Create an enum value from one of the symbols of an enum schema.
+ */
+AvroValue *avro_value_enum_symbol_new(const AvroSchema *schema, AvroStr symbol);
+
 AvroByteArray avro_value_fixed_get(const AvroValue *value);
 
 AvroValue *avro_value_fixed_new(uintptr_t len, AvroByteArray b);
 
+/*
+ * Create a fixed value for a fixed schema, checking that the size of the bytes matches.
+ */
+AvroValue *avro_value_fixed_schema_new(const AvroSchema *schema, AvroByteArray b);
+
 float avro_value_float_get(const AvroValue *value);
 
 AvroValue *avro_value_float_new(float x);
 
 void avro_value_free(AvroValue *v);
 
+/*
+ * Get a nested value following a path such as `a.b[3].c` or `tags["env"]`.
+ *
+ * Records, arrays, maps and non-null unions are walked through. The value is borrowed
+ * from its parent.
+ */
+const AvroValue *avro_value_get_path(const AvroValue *value, const AvroStr *path);
+
 int avro_value_int_get(const AvroValue *value);
 
 AvroValue *avro_value_int_new(int n);
@@ -212,22 +701,172 @@ void avro_value_null_get(const AvroValue *value);
 
 AvroValue *avro_value_null_new(void);
 
+/*
+ * Apply a partial record, given as a record or a map holding some of its fields, to a
+ * record of a schema. Nested records are patched the same way, other fields are replaced.
+ *
+ * The values are not consumed. The patched record is a new value.
+ */
+AvroValue *avro_value_patch(const AvroSchema *schema,
+                            const AvroValue *base,
+                            const AvroValue *patch);
+
+/*
+ * Create a random avro value of a schema. The same seed always gives the same value.
+ *
+ * Default options are used if `options` is null.
+ */
+AvroValue *avro_value_random(const AvroSchema *schema,
+                             uint64_t seed,
+                             const AvroRandomOptions *options);
+
+/*
+ * Number of fields of a record.
+ */
+uintptr_t avro_value_record_field_count(const AvroValue *record);
+
+/*
+ * Get the name of the field of a record at a given position. The name is borrowed from the
+ * record.
+ */
+AvroStr avro_value_record_field_name(const AvroValue *record, uintptr_t index);
+
 const AvroValue *avro_value_record_get(const AvroValue *record, const AvroStr *field);
 
+/*
+ * Get the field of a record at a given position, as found with
+ * `avro_schema_record_field_index`. The value is borrowed from the record.
+ */
+const AvroValue *avro_value_record_get_by_index(const AvroValue *record, uintptr_t index);
+
+/*
+ * Get a field of a record, stepping through unions like `avro_value_record_get_unwrapped`.
+ *
+ * Fields absent from the record fall back to their default in the reader schema of the
+ * record, or to null for nullable fields without a default. The value is a copy, to be
+ * freed with `avro_value_free`.
+ */
+AvroValue *avro_value_record_get_or_default(const AvroValue *record,
+                                            const AvroStr *field,
+                                            const AvroSchema *schema);
+
+/*
+ * Get a field of a record, stepping through unions: a null union gives a null value, and
+ * a non-null union the value it holds. The value is borrowed from the record.
+ */
+const AvroValue *avro_value_record_get_unwrapped(const AvroValue *record, const AvroStr *field);
+
 AvroStr avro_value_string_get(const AvroValue *value);
 
 AvroValue *avro_value_string_new(AvroStr s);
 
+/*
+ * Create a value for the branch at a given index of a union schema: 0 for `null` and 1 for
+ * `T` in a `["null", T]` union, the only unions supported by avro-rs. Any other index is an
+ * error.
+ *
+ * The value is checked against the schema of the branch, and consumed in any case. A null
+ * pointer or a null value can be given for the `null` branch.
+ */
+AvroValue *avro_value_union_branch_new(const AvroSchema *schema,
+                                       uintptr_t branch,
+                                       AvroValue *value);
+
+/*
+ * Create a value of a union schema for the branch with a given type name: `null`, or the
+ * name of `T` (e.g. `long` or the name of a record) for a `["null", T]` union, the only
+ * unions supported by avro-rs.
+ *
+ * The value is checked against the schema of the branch, and consumed in any case. A null
+ * pointer or a null value can be given for the `null` branch.
+ */
+AvroValue *avro_value_union_named_new(const AvroSchema *schema,
+                                      const AvroStr *name,
+                                      AvroValue *value);
+
 AvroValue *avro_value_union_new(AvroValue *value);
 
+/*
+ * Create a value of a union schema, checking it against the schema. Only unions of `null`
+ * and one other type, `["null", T]`, are supported by avro-rs.
+ *
+ * A null pointer or a null value gives the `null` branch, any other value is checked
+ * against `T`. The value is consumed in any case.
+ */
+AvroValue *avro_value_union_schema_new(const AvroSchema *schema, AvroValue *value);
+
+/*
+ * Walk an avro value, firing the callbacks of a visitor.
+ *
+ * Return 0 if the whole value was walked, or the non-zero code of the callback which
+ * stopped the walk.
+ */
+int avro_value_visit(const AvroValue *value, const AvroVisitor *visitor);
+
 /*
  * Append a pickled avro value to an avro writer. Writing is not necessarily happening here.
  * Call `avro_writer_flush` to force an actual write.
+ *
+ * If the value does not match the schema of the writer, the error message gives the path of
+ * the offending value, the expected schema and the Python type found.
  */
 uintptr_t avro_writer_append(AvroWriter *writer, const AvroByteArray *value);
 
 uintptr_t avro_writer_append2(AvroWriter *writer, AvroValue *value);
 
+/*
+ * Append every row of an Arrow record batch, imported through the Arrow C Data Interface,
+ * to an avro writer, returning the number of rows appended. The columns of the batch are
+ * matched by name to the fields of the schema of the writer, missing ones taking their
+ * default. Writing is not necessarily happening here. Call `avro_writer_flush` to force an
+ * actual write.
+ *
+ * The Arrow types are checked against the schema before any row is converted, and no row
+ * is appended unless all of them can be. `schema` and `array` are released in any case.
+ */
+uintptr_t avro_writer_append_arrow(AvroWriter *writer,
+                                   struct ArrowSchema *schema,
+                                   struct ArrowArray *array);
+
+/*
+ * Append a pickled list of avro values to an avro writer, returning the number of values
+ * appended. Writing is not necessarily happening here. Call `avro_writer_flush` to force an
+ * actual write.
+ *
+ * If `errors` is null, no value is appended unless all of them can be. Otherwise the values
+ * which cannot be appended are skipped, and `errors` is set to a pickled list of
+ * `(index, message)` tuples for them, to be freed with `avro_byte_array_free`. The byte array
+ * `errors` points to is overwritten without being freed, so it must not hold one still to be
+ * freed, such as the errors of a previous call.
+ */
+uintptr_t avro_writer_append_batch(AvroWriter *writer,
+                                   const AvroByteArray *values,
+                                   AvroByteArray *errors);
+
+/*
+ * Append a MessagePack-encoded avro value to an avro writer, converted like a pickled value
+ * given to `avro_writer_append`. Writing is not necessarily happening here. Call
+ * `avro_writer_flush` to force an actual write.
+ */
+uintptr_t avro_writer_append_msgpack(AvroWriter *writer, const AvroByteArray *value);
+
+/*
+ * Append `n` random avro values of the writer schema to an avro writer. The same seed
+ * always gives the same values.
+ *
+ * Default options are used if `options` is null.
+ */
+uintptr_t avro_writer_append_random(AvroWriter *writer,
+                                    uint64_t seed,
+                                    const AvroRandomOptions *options,
+                                    uintptr_t n);
+
+/*
+ * Append an avro value to an avro writer, without taking ownership of the value. Writing is
+ * not necessarily happening here. Call `avro_writer_flush` to force an actual write.
+ */
+uintptr_t avro_writer_append_ref(AvroWriter *writer, const AvroValue *value);
+
 /*
  * Flush an avro writer.
  */
@@ -243,4 +882,19 @@ AvroByteArray avro_writer_into_data(AvroWriter *writer);
  */
 AvroWriter *avro_writer_new(const AvroSchema *schema, AvroCodec codec);
 
+/*
+ * Create an avro writer whose output only depends on the values appended to it: map
+ * entries are written sorted by key, and the sync marker is derived from the schema.
+ */
+AvroWriter *avro_writer_new_deterministic(const AvroSchema *schema, AvroCodec codec);
+
+/*
+ * Create an avro writer given an avro schema, an avro codec and the options to convert the
+ * pickled values appended with `avro_writer_append`. Default options are used if `options`
+ * is null.
+ */
+AvroWriter *avro_writer_new_with_options(const AvroSchema *schema,
+                                         AvroCodec codec,
+                                         const AvroConversionOptions *options);
+
 #endif /* AVRO_RS_BINDINGS_H */
@@ -0,0 +1,1689 @@
+//! Export of avro values as Arrow record batches, and import of Arrow record batches as avro
+//! values, through the Arrow C Data Interface.
+//!
+//! Records map to struct arrays, arrays to lists, maps to maps with string keys, nullable unions
+//! to nullable arrays, fixed to fixed-size binaries and enums to dictionary arrays of their
+//! symbols. avro-rs 0.4 does not parse logical types, so values are exported with their
+//! underlying type.
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::{ptr, slice, str};
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use avro_utils::{avro_value_from_json_default, schema_name};
+use failure::{err_msg, Error};
+use path::{Path, PathSegment};
+use reader::{AvroReader, ReaderHandle};
+use writer::{AvroWriter, WriterHandle};
+
+const ARROW_FLAG_NULLABLE: i64 = 2;
+const ARROW_FLAG_MAP_KEYS_SORTED: i64 = 4;
+
+/// Type of an Arrow array, as described by the Arrow C Data Interface.
+#[repr(C)]
+pub struct ArrowSchema {
+    pub format: *const c_char,
+    pub name: *const c_char,
+    pub metadata: *const c_char,
+    pub flags: i64,
+    pub n_children: i64,
+    pub children: *mut *mut ArrowSchema,
+    pub dictionary: *mut ArrowSchema,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub private_data: *mut c_void,
+}
+
+/// Data of an Arrow array, as described by the Arrow C Data Interface.
+#[repr(C)]
+pub struct ArrowArray {
+    pub length: i64,
+    pub null_count: i64,
+    pub offset: i64,
+    pub n_buffers: i64,
+    pub n_children: i64,
+    pub buffers: *mut *const c_void,
+    pub children: *mut *mut ArrowArray,
+    pub dictionary: *mut ArrowArray,
+    pub release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub private_data: *mut c_void,
+}
+
+/// Arrow type of the values of an avro schema.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrowType {
+    Null,
+    Boolean,
+    Int32,
+    Int64,
+    Float32,
+    Float64,
+    Binary,
+    Utf8,
+    FixedSizeBinary(usize),
+    List(Box<ArrowField>),
+    /// A map, given the field of its entries: a struct of a `key` and a `value`.
+    Map(Box<ArrowField>),
+    Struct(Vec<ArrowField>),
+    /// Int32 indexes into the symbols of an enum.
+    Dictionary(Vec<String>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrowField {
+    pub name: String,
+    pub data_type: ArrowType,
+    pub nullable: bool,
+}
+
+impl ArrowField {
+    /// Field of the values of an avro schema. Unions with null are nullable fields of their
+    /// other type.
+    pub fn from_avro(name: &str, schema: &Schema) -> Result<ArrowField, Error> {
+        let (data_type, nullable) = match *schema {
+            Schema::Null => (ArrowType::Null, true),
+            Schema::Boolean => (ArrowType::Boolean, false),
+            Schema::Int => (ArrowType::Int32, false),
+            Schema::Long => (ArrowType::Int64, false),
+            Schema::Float => (ArrowType::Float32, false),
+            Schema::Double => (ArrowType::Float64, false),
+            Schema::Bytes => (ArrowType::Binary, false),
+            Schema::String => (ArrowType::Utf8, false),
+            Schema::Fixed { size, .. } => (ArrowType::FixedSizeBinary(size), false),
+            Schema::Array(ref items) => (
+                ArrowType::List(Box::new(ArrowField::from_avro("item", items)?)),
+                false,
+            ),
+            Schema::Map(ref values) => {
+                let entries = ArrowType::Struct(vec![
+                    ArrowField {
+                        name: "key".to_owned(),
+                        data_type: ArrowType::Utf8,
+                        nullable: false,
+                    },
+                    ArrowField::from_avro("value", values)?,
+                ]);
+                let entries = ArrowField {
+                    name: "entries".to_owned(),
+                    data_type: entries,
+                    nullable: false,
+                };
+                (ArrowType::Map(Box::new(entries)), false)
+            },
+            Schema::Union(ref inner) => {
+                let field = ArrowField::from_avro(name, inner)?;
+                (field.data_type, true)
+            },
+            Schema::Record { ref fields, .. } => (
+                ArrowType::Struct(
+                    fields
+                        .iter()
+                        .map(|field| ArrowField::from_avro(&field.name, &field.schema))
+                        .collect::<Result<_, _>>()?,
+                ),
+                false,
+            ),
+            Schema::Enum { ref symbols, .. } => (ArrowType::Dictionary(symbols.clone()), false),
+        };
+        Ok(ArrowField {
+            name: name.to_owned(),
+            data_type,
+            nullable,
+        })
+    }
+
+    /// Field of the record batches of the values of a record schema.
+    pub fn record_batch(schema: &Schema) -> Result<ArrowField, Error> {
+        match *schema {
+            Schema::Record { .. } => ArrowField::from_avro("", schema),
+            _ => Err(err_msg(
+                "only records can be exported as Arrow record batches",
+            )),
+        }
+    }
+}
+
+fn push_bit(bitmap: &mut Vec<u8>, index: usize, bit: bool) {
+    if bitmap.len() * 8 == index {
+        bitmap.push(0);
+    }
+    if bit {
+        bitmap[index / 8] |= 1 << (index % 8);
+    }
+}
+
+/// Offset of the end of a value in the 32-bit offsets of binaries, strings, lists and maps.
+fn offset(end: usize) -> Result<i32, Error> {
+    i32::try_from(end).map_err(|_| {
+        err_msg(format!(
+            "offset {} does not fit in the 32-bit offsets of Arrow arrays",
+            end
+        ))
+    })
+}
+
+/// Buffers of an Arrow array being built.
+#[derive(Debug)]
+enum ColumnData {
+    Null,
+    /// A bitmap of values.
+    Boolean(Vec<u8>),
+    /// Values of a fixed width: numbers, fixed-size binaries and dictionary indexes.
+    Fixed(Vec<u8>),
+    Binary {
+        offsets: Vec<i32>,
+        values: Vec<u8>,
+    },
+    /// Lists and maps, whose items are their entries.
+    List {
+        offsets: Vec<i32>,
+        items: Box<Column>,
+    },
+    Struct(Vec<Column>),
+}
+
+/// An Arrow array being built from avro values.
+#[derive(Debug)]
+pub struct Column {
+    len: usize,
+    null_count: usize,
+    validity: Vec<u8>,
+    data: ColumnData,
+}
+
+impl Column {
+    pub fn new(data_type: &ArrowType) -> Column {
+        let data = match *data_type {
+            ArrowType::Null => ColumnData::Null,
+            ArrowType::Boolean => ColumnData::Boolean(Vec::new()),
+            ArrowType::Int32
+            | ArrowType::Int64
+            | ArrowType::Float32
+            | ArrowType::Float64
+            | ArrowType::FixedSizeBinary(_)
+            | ArrowType::Dictionary(_) => ColumnData::Fixed(Vec::new()),
+            ArrowType::Binary | ArrowType::Utf8 => ColumnData::Binary {
+                offsets: vec![0],
+                values: Vec::new(),
+            },
+            ArrowType::List(ref items) | ArrowType::Map(ref items) => ColumnData::List {
+                offsets: vec![0],
+                items: Box::new(Column::new(&items.data_type)),
+            },
+            ArrowType::Struct(ref fields) => ColumnData::Struct(
+                fields
+                    .iter()
+                    .map(|field| Column::new(&field.data_type))
+                    .collect(),
+            ),
+        };
+        Column {
+            len: 0,
+            null_count: 0,
+            validity: Vec::new(),
+            data,
+        }
+    }
+
+    fn push_validity(&mut self, valid: bool) {
+        push_bit(&mut self.validity, self.len, valid);
+        if !valid {
+            self.null_count += 1;
+        }
+        self.len += 1;
+    }
+
+    /// Append a placeholder value, null if `valid` is false. Children of structs are null
+    /// too when nullable.
+    fn append_empty(&mut self, data_type: &ArrowType, valid: bool) {
+        match (data_type, &mut self.data) {
+            (_, ColumnData::Null) => (),
+            (_, ColumnData::Boolean(values)) => push_bit(values, self.len, false),
+            (data_type, ColumnData::Fixed(values)) => {
+                let width = match *data_type {
+                    ArrowType::Int64 | ArrowType::Float64 => 8,
+                    ArrowType::FixedSizeBinary(size) => size,
+                    _ => 4,
+                };
+                let len = values.len();
+                values.resize(len + width, 0);
+            },
+            (_, ColumnData::Binary { offsets, .. }) | (_, ColumnData::List { offsets, .. }) => {
+                let last = *offsets.last().unwrap_or(&0);
+                offsets.push(last);
+            },
+            (ArrowType::Struct(fields), ColumnData::Struct(children)) => {
+                for (field, child) in fields.iter().zip(children.iter_mut()) {
+                    child.append_empty(&field.data_type, valid || !field.nullable);
+                }
+            },
+            _ => unreachable!(),
+        }
+        let valid = valid && *data_type != ArrowType::Null;
+        self.push_validity(valid);
+    }
+
+    fn append_str(&mut self, s: &str) -> Result<(), Error> {
+        if let ColumnData::Binary { offsets, values } = &mut self.data {
+            offsets.push(offset(values.len() + s.len())?);
+            values.extend_from_slice(s.as_bytes());
+        }
+        self.push_validity(true);
+        Ok(())
+    }
+
+    /// Append an avro value of the field.
+    pub fn append(&mut self, field: &ArrowField, value: &Value) -> Result<(), Error> {
+        let value = match *value {
+            Value::Union(None) if field.nullable => {
+                self.append_empty(&field.data_type, false);
+                return Ok(())
+            },
+            Value::Union(Some(ref value)) if field.nullable => &**value,
+            ref value => value,
+        };
+
+        match (&field.data_type, &mut self.data, value) {
+            (ArrowType::Null, ColumnData::Null, Value::Null) => {
+                self.append_empty(&field.data_type, false);
+                return Ok(())
+            },
+            (ArrowType::Boolean, ColumnData::Boolean(values), &Value::Boolean(b)) => {
+                push_bit(values, self.len, b)
+            },
+            (ArrowType::Int32, ColumnData::Fixed(values), &Value::Int(n)) => {
+                values.extend_from_slice(&n.to_le_bytes())
+            },
+            (ArrowType::Int64, ColumnData::Fixed(values), &Value::Long(n)) => {
+                values.extend_from_slice(&n.to_le_bytes())
+            },
+            (ArrowType::Float32, ColumnData::Fixed(values), &Value::Float(x)) => {
+                values.extend_from_slice(&x.to_bits().to_le_bytes())
+            },
+            (ArrowType::Float64, ColumnData::Fixed(values), &Value::Double(x)) => {
+                values.extend_from_slice(&x.to_bits().to_le_bytes())
+            },
+            (
+                ArrowType::FixedSizeBinary(size),
+                ColumnData::Fixed(values),
+                Value::Fixed(_, bytes),
+            ) if bytes.len() == *size => values.extend_from_slice(bytes),
+            (ArrowType::Dictionary(_), ColumnData::Fixed(values), &Value::Enum(index, _)) => {
+                values.extend_from_slice(&index.to_le_bytes())
+            },
+            (ArrowType::Binary, ColumnData::Binary { offsets, values }, Value::Bytes(bytes)) => {
+                offsets.push(offset(values.len() + bytes.len())?);
+                values.extend_from_slice(bytes);
+            },
+            (ArrowType::Utf8, ColumnData::Binary { offsets, values }, Value::String(s)) => {
+                offsets.push(offset(values.len() + s.len())?);
+                values.extend_from_slice(s.as_bytes());
+            },
+            (ArrowType::List(item), ColumnData::List { offsets, items }, Value::Array(values)) => {
+                let end = offset(items.len + values.len())?;
+                for value in values {
+                    items.append(item, value)?;
+                }
+                offsets.push(end);
+            },
+            (ArrowType::Map(entry), ColumnData::List { offsets, items }, Value::Map(values)) => {
+                let value_field = match entry.data_type {
+                    ArrowType::Struct(ref fields) => &fields[1],
+                    _ => unreachable!(),
+                };
+                let end = offset(items.len + values.len())?;
+                // keys are sorted, as announced by the flags of the schema
+                let mut keys = values.keys().collect::<Vec<_>>();
+                keys.sort();
+                for key in keys {
+                    if let ColumnData::Struct(children) = &mut items.data {
+                        children[0].append_str(key)?;
+                        children[1].append(value_field, &values[key])?;
+                    }
+                    items.push_validity(true);
+                }
+                offsets.push(end);
+            },
+            (ArrowType::Struct(fields), ColumnData::Struct(children), Value::Record(values))
+                if values.len() == fields.len() =>
+            {
+                for ((field, child), (_, value)) in fields.iter().zip(children).zip(values) {
+                    child.append(field, value)?;
+                }
+            },
+            _ => {
+                return Err(err_msg(format!(
+                    "value does not match field `{}`",
+                    field.name
+                )))
+            },
+        }
+        self.push_validity(true);
+        Ok(())
+    }
+}
+
+/// Owned strings and children of an exported schema.
+struct SchemaPrivate {
+    _format: CString,
+    _name: CString,
+    children: Vec<*mut ArrowSchema>,
+    dictionary: *mut ArrowSchema,
+}
+
+unsafe fn release_child_schema(schema: *mut ArrowSchema) {
+    if let Some(release) = (*schema).release {
+        release(schema);
+    }
+    drop(Box::from_raw(schema));
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    if schema.is_null() || (*schema).release.is_none() {
+        return
+    }
+    let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+    for &child in &private.children {
+        release_child_schema(child);
+    }
+    if !private.dictionary.is_null() {
+        release_child_schema(private.dictionary);
+    }
+    (*schema).release = None;
+}
+
+fn schema_of(
+    format: &str,
+    name: &str,
+    flags: i64,
+    children: Vec<ArrowSchema>,
+    dictionary: Option<ArrowSchema>,
+) -> ArrowSchema {
+    let format = CString::new(format).unwrap_or_default();
+    let name = CString::new(name).unwrap_or_default();
+    let mut private = Box::new(SchemaPrivate {
+        children: children
+            .into_iter()
+            .map(|child| Box::into_raw(Box::new(child)))
+            .collect(),
+        dictionary: dictionary.map_or(ptr::null_mut(), |dictionary| {
+            Box::into_raw(Box::new(dictionary))
+        }),
+        _format: format,
+        _name: name,
+    });
+    ArrowSchema {
+        format: private._format.as_ptr(),
+        name: private._name.as_ptr(),
+        metadata: ptr::null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: private.dictionary,
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+/// Export the type of a field through the Arrow C Data Interface.
+pub fn export_schema(field: &ArrowField) -> ArrowSchema {
+    let mut flags = if field.nullable {
+        ARROW_FLAG_NULLABLE
+    } else {
+        0
+    };
+    let mut children = Vec::new();
+    let mut dictionary = None;
+    let format = match field.data_type {
+        ArrowType::Null => "n".to_owned(),
+        ArrowType::Boolean => "b".to_owned(),
+        ArrowType::Int32 => "i".to_owned(),
+        ArrowType::Int64 => "l".to_owned(),
+        ArrowType::Float32 => "f".to_owned(),
+        ArrowType::Float64 => "g".to_owned(),
+        ArrowType::Binary => "z".to_owned(),
+        ArrowType::Utf8 => "u".to_owned(),
+        ArrowType::FixedSizeBinary(size) => format!("w:{}", size),
+        ArrowType::List(ref item) => {
+            children.push(export_schema(item));
+            "+l".to_owned()
+        },
+        ArrowType::Map(ref entries) => {
+            flags |= ARROW_FLAG_MAP_KEYS_SORTED;
+            children.push(export_schema(entries));
+            "+m".to_owned()
+        },
+        ArrowType::Struct(ref fields) => {
+            children.extend(fields.iter().map(export_schema));
+            "+s".to_owned()
+        },
+        ArrowType::Dictionary(_) => {
+            dictionary = Some(schema_of("u", "", 0, Vec::new(), None));
+            "i".to_owned()
+        },
+    };
+    schema_of(&format, &field.name, flags, children, dictionary)
+}
+
+/// Owned buffers and children of an exported array.
+struct ArrayPrivate {
+    _buffers: Vec<Vec<u8>>,
+    buffer_ptrs: Vec<*const c_void>,
+    children: Vec<*mut ArrowArray>,
+    dictionary: *mut ArrowArray,
+}
+
+unsafe fn release_child_array(array: *mut ArrowArray) {
+    if let Some(release) = (*array).release {
+        release(array);
+    }
+    drop(Box::from_raw(array));
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    if array.is_null() || (*array).release.is_none() {
+        return
+    }
+    let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+    for &child in &private.children {
+        release_child_array(child);
+    }
+    if !private.dictionary.is_null() {
+        release_child_array(private.dictionary);
+    }
+    (*array).release = None;
+}
+
+fn offsets_buffer(offsets: Vec<i32>) -> Vec<u8> {
+    offsets
+        .iter()
+        .flat_map(|offset| offset.to_le_bytes().to_vec())
+        .collect()
+}
+
+fn array_of(
+    len: usize,
+    null_count: usize,
+    buffers: Vec<Option<Vec<u8>>>,
+    children: Vec<ArrowArray>,
+    dictionary: Option<ArrowArray>,
+) -> ArrowArray {
+    let buffer_ptrs = buffers
+        .iter()
+        .map(|buffer| match *buffer {
+            Some(ref buffer) => buffer.as_ptr() as *const c_void,
+            None => ptr::null(),
+        })
+        .collect();
+    let mut private = Box::new(ArrayPrivate {
+        _buffers: buffers.into_iter().flatten().collect(),
+        buffer_ptrs,
+        children: children
+            .into_iter()
+            .map(|child| Box::into_raw(Box::new(child)))
+            .collect(),
+        dictionary: dictionary.map_or(ptr::null_mut(), |dictionary| {
+            Box::into_raw(Box::new(dictionary))
+        }),
+    });
+    ArrowArray {
+        length: len as i64,
+        null_count: null_count as i64,
+        offset: 0,
+        n_buffers: private.buffer_ptrs.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffer_ptrs.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: private.dictionary,
+        release: Some(release_array),
+        private_data: Box::into_raw(private) as *mut c_void,
+    }
+}
+
+/// Export the values of a column of a field through the Arrow C Data Interface.
+pub fn export_array(field: &ArrowField, column: Column) -> ArrowArray {
+    let Column {
+        len,
+        null_count,
+        validity,
+        data,
+    } = column;
+    // the validity bitmap can be omitted when there are no nulls
+    let validity = if null_count > 0 { Some(validity) } else { None };
+
+    match (&field.data_type, data) {
+        (_, ColumnData::Null) => array_of(len, len, Vec::new(), Vec::new(), None),
+        (ArrowType::Dictionary(symbols), ColumnData::Fixed(indexes)) => {
+            let mut dictionary = Column::new(&ArrowType::Utf8);
+            for symbol in symbols {
+                dictionary
+                    .append_str(symbol)
+                    .expect("enum symbols fit in 32-bit offsets");
+            }
+            let dictionary_field = ArrowField {
+                name: String::new(),
+                data_type: ArrowType::Utf8,
+                nullable: false,
+            };
+            let dictionary = export_array(&dictionary_field, dictionary);
+            array_of(
+                len,
+                null_count,
+                vec![validity, Some(indexes)],
+                Vec::new(),
+                Some(dictionary),
+            )
+        },
+        (_, ColumnData::Boolean(values)) | (_, ColumnData::Fixed(values)) => array_of(
+            len,
+            null_count,
+            vec![validity, Some(values)],
+            Vec::new(),
+            None,
+        ),
+        (_, ColumnData::Binary { offsets, values }) => array_of(
+            len,
+            null_count,
+            vec![validity, Some(offsets_buffer(offsets)), Some(values)],
+            Vec::new(),
+            None,
+        ),
+        (ArrowType::List(item), ColumnData::List { offsets, items })
+        | (ArrowType::Map(item), ColumnData::List { offsets, items }) => array_of(
+            len,
+            null_count,
+            vec![validity, Some(offsets_buffer(offsets))],
+            vec![export_array(item, *items)],
+            None,
+        ),
+        (ArrowType::Struct(fields), ColumnData::Struct(children)) => array_of(
+            len,
+            null_count,
+            vec![validity],
+            fields
+                .iter()
+                .zip(children)
+                .map(|(field, child)| export_array(field, child))
+                .collect(),
+            None,
+        ),
+        _ => unreachable!(),
+    }
+}
+
+/// Field of the record batches read out of an avro reader: the reader schema if any, else the
+/// schema of the data.
+fn reader_field(handle: &ReaderHandle) -> Result<ArrowField, Error> {
+    let reader = &handle.reader;
+    ArrowField::record_batch(
+        reader
+            .reader_schema()
+            .unwrap_or_else(|| reader.writer_schema()),
+    )
+}
+
+/// Read up to `batch_size` records out of an avro reader into a column of their field. Errors
+/// are handled as by `ReaderHandle::read_values`, records which cannot be appended to the column
+/// included: reading stops at them, and they are not consumed past.
+fn read_column(
+    handle: &mut ReaderHandle,
+    field: &ArrowField,
+    batch_size: usize,
+) -> Result<Column, Error> {
+    let mut column = Column::new(&field.data_type);
+    // records are read one at a time, so that none is consumed past one which cannot be appended
+    let mut values = Vec::new();
+    while values.len() < batch_size {
+        let value = match handle.next_value() {
+            None => break,
+            Some(Ok(value)) => value,
+            Some(Err(err)) if values.is_empty() => return Err(err),
+            Some(Err(err)) => {
+                handle.error = Some(err);
+                break
+            },
+        };
+        if let Err(err) = column.append(field, &value) {
+            if values.is_empty() {
+                return Err(err)
+            }
+            handle.error = Some(err);
+            // the column may hold part of the failing record, build it again without it
+            column = Column::new(&field.data_type);
+            for value in &values {
+                column.append(field, value)?;
+            }
+            break
+        }
+        values.push(value);
+    }
+    Ok(column)
+}
+
+ffi_fn! {
+    /// Export the type of the record batches read with `avro_reader_read_arrow` to `schema`,
+    /// through the Arrow C Data Interface. The schema must be released by its consumer.
+    unsafe fn avro_reader_arrow_schema(
+        reader: *mut AvroReader,
+        schema: *mut ArrowSchema
+    ) -> Result<()> {
+        let field = reader_field(&*(reader as *mut ReaderHandle))?;
+        ptr::write(schema, export_schema(&field));
+        Ok(())
+    }
+}
+
+ffi_fn! {
+    /// Read up to `batch_size` records out of an avro reader into an Arrow record batch,
+    /// exported through the Arrow C Data Interface to `schema` and `array`, which must be
+    /// released by their consumer. The schema of the data must be a record.
+    ///
+    /// Return the number of records read. Once all of them are read, 0 is returned and neither
+    /// `schema` nor `array` is written. Errors are reported as by `avro_reader_read_batch`: the
+    /// records read before an error are returned, and the error by the next read. This holds
+    /// for records which cannot be converted to Arrow as well.
+    ///
+    /// avro-rs 0.4 does not parse logical types, so values of a logical type come out as their
+    /// underlying type: e.g. `timestamp-millis` longs as `int64` rather than timestamps, and
+    /// `decimal` bytes as `binary`.
+    unsafe fn avro_reader_read_arrow(
+        reader: *mut AvroReader,
+        batch_size: usize,
+        schema: *mut ArrowSchema,
+        array: *mut ArrowArray
+    ) -> Result<usize> {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        let field = reader_field(handle)?;
+        let column = read_column(handle, &field, batch_size)?;
+
+        let len = column.len;
+        if len > 0 {
+            ptr::write(schema, export_schema(&field));
+            ptr::write(array, export_array(&field, column));
+        }
+        Ok(len)
+    }
+}
+
+/// Physical layout of an imported Arrow array, given by the format of its schema.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Layout {
+    Null,
+    Boolean,
+    /// Integers, and dates, times, timestamps and durations stored as integers.
+    Int {
+        width: usize,
+        signed: bool,
+    },
+    Float32,
+    Float64,
+    Binary {
+        large: bool,
+        utf8: bool,
+    },
+    FixedSizeBinary(usize),
+    List {
+        large: bool,
+    },
+    Map,
+    Struct,
+}
+
+impl Layout {
+    fn parse(format: &str) -> Result<Layout, Error> {
+        let int = |width, signed| Layout::Int { width, signed };
+        let layout = match format {
+            "n" => Layout::Null,
+            "b" => Layout::Boolean,
+            "c" => int(1, true),
+            "C" => int(1, false),
+            "s" => int(2, true),
+            "S" => int(2, false),
+            "i" | "tdD" | "tts" | "ttm" => int(4, true),
+            "I" => int(4, false),
+            "l" | "tdm" | "ttu" | "ttn" => int(8, true),
+            "L" => int(8, false),
+            "f" => Layout::Float32,
+            "g" => Layout::Float64,
+            "z" => Layout::Binary {
+                large: false,
+                utf8: false,
+            },
+            "Z" => Layout::Binary {
+                large: true,
+                utf8: false,
+            },
+            "u" => Layout::Binary {
+                large: false,
+                utf8: true,
+            },
+            "U" => Layout::Binary {
+                large: true,
+                utf8: true,
+            },
+            "+l" => Layout::List { large: false },
+            "+L" => Layout::List { large: true },
+            "+m" => Layout::Map,
+            "+s" => Layout::Struct,
+            _ if format.starts_with("ts") || format.starts_with("tD") => int(8, true),
+            _ if format.starts_with("w:") => format[2..]
+                .parse()
+                .map(Layout::FixedSizeBinary)
+                .map_err(|_| err_msg(format!("invalid Arrow format `{}`", format)))?,
+            _ => {
+                return Err(err_msg(format!(
+                    "Arrow format `{}` is not supported",
+                    format
+                )))
+            },
+        };
+        Ok(layout)
+    }
+
+    /// Number of buffers of arrays of this layout, including the validity bitmap.
+    fn n_buffers(self) -> i64 {
+        match self {
+            Layout::Null => 0,
+            Layout::Struct => 1,
+            Layout::Binary { .. } => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// How the values of an imported Arrow array are converted to values of an avro schema, as
+/// checked once for the whole array.
+enum Conversion<'a> {
+    Value,
+    List(Box<Import<'a>>),
+    /// Keys and values of the entries of maps, along with the offset and length of the entries.
+    Map {
+        offset: usize,
+        length: i64,
+        keys: Box<Import<'a>>,
+        values: Box<Import<'a>>,
+    },
+    /// Values of the avro fields: Arrow children, or the defaults of missing fields.
+    Record(Vec<Result<Import<'a>, Value>>),
+    /// Indexes into dictionary values.
+    Dictionary(Box<Import<'a>>),
+}
+
+/// An imported Arrow array, along with the avro schema its values are converted to.
+struct Import<'a> {
+    path: Path,
+    layout: Layout,
+    array: &'a ArrowArray,
+    /// The schema of the values, without the union with null if `nullable`.
+    schema: &'a Schema,
+    nullable: bool,
+    conversion: Conversion<'a>,
+}
+
+/// A string of an Arrow schema, empty if null.
+unsafe fn c_str<'a>(s: *const c_char) -> Cow<'a, str> {
+    if s.is_null() {
+        Cow::Borrowed("")
+    } else {
+        CStr::from_ptr(s).to_string_lossy()
+    }
+}
+
+/// Location of an imported value in error messages.
+fn at(path: &Path) -> String {
+    if path.0.is_empty() {
+        String::new()
+    } else {
+        format!(" at `{}`", path)
+    }
+}
+
+/// Check the length, offset and buffers of an Arrow array before any of its values is read.
+/// Only the validity bitmap may be null, and the other buffers only if the array is empty.
+unsafe fn check_array(path: &Path, array: &ArrowArray, n_buffers: i64) -> Result<(), Error> {
+    let malformed = |reason: String| {
+        Err(err_msg(format!(
+            "malformed Arrow array{}: {}",
+            at(path),
+            reason
+        )))
+    };
+    if array.length < 0 || array.offset < 0 || array.offset.checked_add(array.length).is_none() {
+        return malformed(format!(
+            "invalid length {} or offset {}",
+            array.length, array.offset
+        ))
+    }
+    if array.n_buffers != n_buffers {
+        return malformed(format!(
+            "{} buffers instead of {}",
+            array.n_buffers, n_buffers
+        ))
+    }
+    if n_buffers > 0 && array.buffers.is_null() {
+        return malformed("null buffers".to_owned())
+    }
+    if array.length > 0 {
+        if let Some(index) = (1..n_buffers).find(|&i| (*array.buffers.add(i as usize)).is_null()) {
+            return malformed(format!("null buffer {}", index))
+        }
+    }
+    Ok(())
+}
+
+/// Check the children of an Arrow array and of its schema, which must be at least `min_length`
+/// long.
+unsafe fn check_children(
+    path: &Path,
+    arrow_schema: &ArrowSchema,
+    array: &ArrowArray,
+    n_children: i64,
+    min_length: i64,
+) -> Result<(), Error> {
+    let malformed = |reason: &str| {
+        Err(err_msg(format!(
+            "malformed Arrow array{}: {}",
+            at(path),
+            reason
+        )))
+    };
+    if arrow_schema.n_children != n_children || array.n_children != n_children {
+        return malformed("unexpected number of children")
+    }
+    if n_children > 0 && (arrow_schema.children.is_null() || array.children.is_null()) {
+        return malformed("null children")
+    }
+    for index in 0..n_children as usize {
+        let child = *array.children.add(index);
+        if (*arrow_schema.children.add(index)).is_null() || child.is_null() {
+            return malformed("null child")
+        }
+        if (*child).length < min_length {
+            return malformed("child shorter than its parent")
+        }
+    }
+    Ok(())
+}
+
+impl<'a> Import<'a> {
+    /// Check that the values of an Arrow array can be converted to an avro schema.
+    unsafe fn new(
+        path: Path,
+        arrow_schema: &'a ArrowSchema,
+        array: &'a ArrowArray,
+        schema: &'a Schema,
+    ) -> Result<Import<'a>, Error> {
+        let format = c_str(arrow_schema.format);
+        let (schema, nullable) = match *schema {
+            Schema::Union(ref inner) => (&**inner, true),
+            ref schema => (schema, false),
+        };
+        let mismatch = || {
+            err_msg(format!(
+                "Arrow format `{}`{} cannot be written as avro {}",
+                format,
+                at(&path),
+                schema_name(schema)
+            ))
+        };
+
+        if !arrow_schema.dictionary.is_null() {
+            check_array(&path, array, 2)?;
+            if array.dictionary.is_null() {
+                return Err(err_msg(format!("malformed Arrow array{}", at(&path))))
+            }
+            let layout = match Layout::parse(&format)? {
+                layout @ Layout::Int { .. } => layout,
+                _ => return Err(mismatch()),
+            };
+            let values = Import::new(
+                path.clone(),
+                &*arrow_schema.dictionary,
+                &*array.dictionary,
+                schema,
+            )?;
+            return Ok(Import {
+                path,
+                layout,
+                array,
+                schema,
+                nullable,
+                conversion: Conversion::Dictionary(Box::new(values)),
+            })
+        }
+
+        let layout = Layout::parse(&format)?;
+        let n_children = match layout {
+            Layout::List { .. } | Layout::Map => 1,
+            Layout::Struct => arrow_schema.n_children,
+            _ => 0,
+        };
+        check_array(&path, array, layout.n_buffers())?;
+        // the rows of a struct are at the same indexes in its children
+        let min_length = match layout {
+            Layout::Struct => array.offset + array.length,
+            _ => 0,
+        };
+        check_children(&path, arrow_schema, array, n_children, min_length)?;
+        let child = |index: usize, path: Path, schema: &'a Schema| {
+            let arrow_schema = &**arrow_schema.children.add(index);
+            let array = &**array.children.add(index);
+            Import::new(path, arrow_schema, array, schema)
+        };
+
+        let conversion = match (layout, schema) {
+            (Layout::Null, _) if nullable || *schema == Schema::Null => Conversion::Value,
+            (Layout::Boolean, Schema::Boolean)
+            | (Layout::Int { .. }, Schema::Long)
+            | (Layout::Float32, Schema::Float)
+            | (Layout::Float32, Schema::Double)
+            | (Layout::Float64, Schema::Double)
+            | (Layout::Binary { .. }, Schema::Bytes)
+            | (Layout::Binary { utf8: true, .. }, Schema::String)
+            | (Layout::Binary { utf8: true, .. }, Schema::Enum { .. })
+            | (Layout::FixedSizeBinary(_), Schema::Bytes) => Conversion::Value,
+            (Layout::Int { width, signed }, Schema::Int) if width < 4 || (width == 4 && signed) => {
+                Conversion::Value
+            },
+            (Layout::FixedSizeBinary(len), Schema::Fixed { size, .. }) if len == *size => {
+                Conversion::Value
+            },
+            (Layout::List { .. }, Schema::Array(items)) => {
+                let items = child(0, path.child(PathSegment::Index(0)), items)?;
+                Conversion::List(Box::new(items))
+            },
+            (Layout::Map, Schema::Map(values)) => {
+                let entries = &**arrow_schema.children;
+                let entries_array = &**array.children;
+                if c_str(entries.format) != "+s" {
+                    return Err(err_msg(format!("malformed Arrow map{}", at(&path))))
+                }
+                check_array(&path, entries_array, 1)?;
+                let length = entries_array.length;
+                let min_length = entries_array.offset + length;
+                check_children(&path, entries, entries_array, 2, min_length)?;
+                let entry = |index: usize, path: Path, schema: &'a Schema| {
+                    let arrow_schema = &**entries.children.add(index);
+                    let array = &**entries_array.children.add(index);
+                    Import::new(path, arrow_schema, array, schema)
+                };
+                let keys = entry(0, path.clone(), &Schema::String)
+                    .map_err(|_| err_msg(format!("Arrow map keys{} are not strings", at(&path))))?;
+                let values = entry(1, path.child(PathSegment::Key(String::new())), values)?;
+                Conversion::Map {
+                    offset: entries_array.offset as usize,
+                    length,
+                    keys: Box::new(keys),
+                    values: Box::new(values),
+                }
+            },
+            (Layout::Struct, Schema::Record { fields, .. }) => {
+                let names = (0..arrow_schema.n_children as usize)
+                    .map(|index| {
+                        let child = &**arrow_schema.children.add(index);
+                        c_str(child.name).into_owned()
+                    })
+                    .collect::<Vec<_>>();
+                let extra = names
+                    .iter()
+                    .filter(|name| !fields.iter().any(|field| field.name == **name))
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>();
+                if !extra.is_empty() {
+                    return Err(err_msg(format!(
+                        "Arrow columns{} are not fields of the record: {}",
+                        at(&path),
+                        extra.join(", ")
+                    )))
+                }
+
+                let mut values = Vec::with_capacity(fields.len());
+                for field in fields {
+                    let path = path.child(PathSegment::Field(field.name.clone()));
+                    let value = match names.iter().position(|name| *name == field.name) {
+                        Some(index) => Ok(child(index, path, &field.schema)?),
+                        None => Err(match (field.default.as_ref(), &field.schema) {
+                            (Some(default), schema) => {
+                                avro_value_from_json_default(schema, default)
+                                    .map_err(|err| err_msg(format!("field `{}`: {}", path, err)))?
+                            },
+                            (None, Schema::Union(_)) => Value::Union(None),
+                            (None, _) => {
+                                return Err(err_msg(format!(
+                                    "no Arrow column for required field `{}`",
+                                    path
+                                )))
+                            },
+                        }),
+                    };
+                    values.push(value);
+                }
+                Conversion::Record(values)
+            },
+            _ => return Err(mismatch()),
+        };
+
+        Ok(Import {
+            path,
+            layout,
+            array,
+            schema,
+            nullable,
+            conversion,
+        })
+    }
+
+    unsafe fn buffer(&self, index: usize) -> *const u8 {
+        *self.array.buffers.add(index) as *const u8
+    }
+
+    unsafe fn bit(&self, buffer: usize, index: usize) -> bool {
+        *self.buffer(buffer).add(index / 8) & (1 << (index % 8)) != 0
+    }
+
+    /// The integer at an index of the data buffer.
+    unsafe fn int(&self, index: usize) -> Result<i64, Error> {
+        let data = self.buffer(1);
+        let n = match self.layout {
+            Layout::Int { width: 1, signed } => {
+                let n = *data.add(index);
+                if signed {
+                    i64::from(n as i8)
+                } else {
+                    i64::from(n)
+                }
+            },
+            Layout::Int { width: 2, signed } => {
+                let n = ptr::read_unaligned(data.add(2 * index) as *const u16);
+                if signed {
+                    i64::from(n as i16)
+                } else {
+                    i64::from(n)
+                }
+            },
+            Layout::Int { width: 4, signed } => {
+                let n = ptr::read_unaligned(data.add(4 * index) as *const u32);
+                if signed {
+                    i64::from(n as i32)
+                } else {
+                    i64::from(n)
+                }
+            },
+            Layout::Int { signed, .. } => {
+                let n = ptr::read_unaligned(data.add(8 * index) as *const u64);
+                if !signed && n > i64::MAX as u64 {
+                    return Err(err_msg(format!(
+                        "{}{} does not fit in avro long",
+                        n,
+                        at(&self.path)
+                    )))
+                }
+                n as i64
+            },
+            _ => unreachable!(),
+        };
+        Ok(n)
+    }
+
+    /// The offset at an index of the offsets buffer of a binary or list.
+    unsafe fn offset_at(&self, index: usize) -> i64 {
+        let offsets = self.buffer(1);
+        match self.layout {
+            Layout::Binary { large: true, .. } | Layout::List { large: true } => {
+                ptr::read_unaligned((offsets as *const i64).add(index))
+            },
+            _ => i64::from(ptr::read_unaligned((offsets as *const i32).add(index))),
+        }
+    }
+
+    /// The start and end offsets of a binary or list at an index, checked to be in order and
+    /// at most `limit`: the length of the child array, or the end offset of the last binary.
+    unsafe fn offsets(&self, index: usize, limit: i64) -> Result<(usize, usize), Error> {
+        let (start, end) = (self.offset_at(index), self.offset_at(index + 1));
+        if start < 0 || start > end || end > limit {
+            return Err(err_msg(format!(
+                "Arrow offsets {}..{}{} are out of bounds",
+                start,
+                end,
+                at(&self.path)
+            )))
+        }
+        Ok((start as usize, end as usize))
+    }
+
+    unsafe fn bytes(&self, index: usize) -> Result<&'a [u8], Error> {
+        let (start, end) = match self.layout {
+            Layout::FixedSizeBinary(size) => (size * index, size * (index + 1)),
+            _ => {
+                let last = (self.array.offset + self.array.length) as usize;
+                self.offsets(index, self.offset_at(last))?
+            },
+        };
+        let data = *self
+            .array
+            .buffers
+            .add(if self.layout.n_buffers() == 3 { 2 } else { 1 });
+        Ok(slice::from_raw_parts(
+            (data as *const u8).add(start),
+            end - start,
+        ))
+    }
+
+    unsafe fn string(&self, index: usize) -> Result<&'a str, Error> {
+        str::from_utf8(self.bytes(index)?)
+            .map_err(|_| err_msg(format!("invalid UTF-8 string{}", at(&self.path))))
+    }
+
+    /// The avro value at an index of the array, not counting its offset.
+    unsafe fn value(&self, index: usize) -> Result<Value, Error> {
+        let index = index + self.array.offset as usize;
+        let valid = match self.layout {
+            Layout::Null => false,
+            _ => self.buffer(0).is_null() || self.bit(0, index),
+        };
+        if !valid {
+            return match *self.schema {
+                _ if self.nullable => Ok(Value::Union(None)),
+                Schema::Null => Ok(Value::Null),
+                _ => Err(err_msg(format!(
+                    "null{} cannot be written as avro {}",
+                    at(&self.path),
+                    schema_name(self.schema)
+                ))),
+            }
+        }
+
+        let value = match (&self.conversion, self.schema) {
+            (Conversion::Dictionary(values), _) => {
+                let key = self.int(index)?;
+                if key < 0 || key >= values.array.length {
+                    return Err(err_msg(format!(
+                        "dictionary index {}{} is out of bounds",
+                        key,
+                        at(&self.path)
+                    )))
+                }
+                values.value(key as usize)?
+            },
+            (Conversion::List(items), _) => {
+                let (start, end) = self.offsets(index, items.array.length)?;
+                Value::Array(
+                    (start..end)
+                        .map(|i| items.value(i))
+                        .collect::<Result<_, _>>()?,
+                )
+            },
+            (
+                Conversion::Map {
+                    offset,
+                    length,
+                    keys,
+                    values,
+                },
+                _,
+            ) => {
+                let (start, end) = self.offsets(index, *length)?;
+                let mut map = HashMap::with_capacity(end - start);
+                for i in start + offset..end + offset {
+                    let key = keys.string(i + keys.array.offset as usize)?;
+                    map.insert(key.to_owned(), values.value(i)?);
+                }
+                Value::Map(map)
+            },
+            (Conversion::Record(values), Schema::Record { fields, .. }) => Value::Record(
+                fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| {
+                        let value = match *value {
+                            Ok(ref column) => column.value(index)?,
+                            Err(ref default) => default.clone(),
+                        };
+                        Ok((field.name.clone(), value))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+            (_, Schema::Boolean) => Value::Boolean(self.bit(1, index)),
+            (_, Schema::Int) => Value::Int(self.int(index)? as i32),
+            (_, Schema::Long) => Value::Long(self.int(index)?),
+            (_, Schema::Float) => {
+                let data = self.buffer(1) as *const f32;
+                Value::Float(ptr::read_unaligned(data.add(index)))
+            },
+            (_, Schema::Double) => match self.layout {
+                Layout::Float32 => {
+                    let data = self.buffer(1) as *const f32;
+                    Value::Double(f64::from(ptr::read_unaligned(data.add(index))))
+                },
+                _ => {
+                    let data = self.buffer(1) as *const f64;
+                    Value::Double(ptr::read_unaligned(data.add(index)))
+                },
+            },
+            (_, Schema::Bytes) => Value::Bytes(self.bytes(index)?.to_vec()),
+            (_, Schema::String) => Value::String(self.string(index)?.to_owned()),
+            (_, Schema::Fixed { size, .. }) => Value::Fixed(*size, self.bytes(index)?.to_vec()),
+            (_, Schema::Enum { symbols, .. }) => {
+                let symbol = self.string(index)?;
+                match symbols.iter().position(|s| s == symbol) {
+                    Some(i) => Value::Enum(i as i32, symbol.to_owned()),
+                    None => {
+                        return Err(err_msg(format!(
+                            "symbol `{}`{} is not in enum `{}`",
+                            symbol,
+                            at(&self.path),
+                            schema_name(self.schema)
+                        )))
+                    },
+                }
+            },
+            _ => unreachable!(),
+        };
+
+        if self.nullable {
+            Ok(Value::Union(Some(Box::new(value))))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// Convert the rows of an Arrow record batch to values of a record schema. All the rows are
+/// converted before any is returned.
+///
+/// # Safety
+///
+/// `arrow_schema` and `array` must follow the Arrow C Data Interface. Their lengths, offsets,
+/// pointers and dictionary indexes are checked, but their buffers must be as large as these
+/// imply.
+pub unsafe fn avro_values_from_arrow(
+    arrow_schema: &ArrowSchema,
+    array: &ArrowArray,
+    schema: &Schema,
+) -> Result<Vec<Value>, Error> {
+    if c_str(arrow_schema.format) != "+s" {
+        return Err(err_msg("only Arrow record batches can be imported"))
+    }
+    let import = Import::new(Path::default(), arrow_schema, array, schema)?;
+    (0..array.length as usize)
+        .map(|row| {
+            import
+                .value(row)
+                .map_err(|err| err_msg(format!("row {}: {}", row, err)))
+        })
+        .collect()
+}
+
+/// Releases an imported schema and array, which the importer owns.
+struct Imported(*mut ArrowSchema, *mut ArrowArray);
+
+impl Drop for Imported {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(release) = (*self.0).release {
+                release(self.0);
+            }
+            if let Some(release) = (*self.1).release {
+                release(self.1);
+            }
+        }
+    }
+}
+
+ffi_fn! {
+    /// Append every row of an Arrow record batch, imported through the Arrow C Data Interface,
+    /// to an avro writer, returning the number of rows appended. The columns of the batch are
+    /// matched by name to the fields of the schema of the writer, missing ones taking their
+    /// default. Writing is not necessarily happening here. Call `avro_writer_flush` to force an
+    /// actual write.
+    ///
+    /// The Arrow types are checked against the schema before any row is converted, and no row
+    /// is appended unless all of them can be. `schema` and `array` are released in any case.
+    unsafe fn avro_writer_append_arrow(
+        writer: *mut AvroWriter,
+        schema: *mut ArrowSchema,
+        array: *mut ArrowArray
+    ) -> Result<usize> {
+        let imported = Imported(schema, array);
+        let writer = &mut (*(writer as *mut WriterHandle)).writer;
+        let values = avro_values_from_arrow(&*imported.0, &*imported.1, writer.schema())?;
+        for value in &values {
+            writer.append_value_ref(value)?;
+        }
+        Ok(values.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::ffi::CStr;
+    use std::slice;
+
+    fn schema() -> Schema {
+        Schema::parse_str(
+            r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {
+                "type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]
+            }},
+            {"name": "tags", "type": {"type": "map", "values": {"type": "array", "items": "int"}}}
+        ]}
+        "#,
+        )
+        .unwrap()
+    }
+
+    fn record(id: i64, comment: Option<&str>, tags: &[(&str, &[i32])]) -> Value {
+        Value::Record(vec![
+            ("id".to_owned(), Value::Long(id)),
+            (
+                "comment".to_owned(),
+                Value::Union(comment.map(|s| Box::new(Value::String(s.to_owned())))),
+            ),
+            ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+            (
+                "tags".to_owned(),
+                Value::Map(
+                    tags.iter()
+                        .map(|&(key, items)| {
+                            let items = items.iter().map(|&n| Value::Int(n)).collect();
+                            (key.to_owned(), Value::Array(items))
+                        })
+                        .collect::<HashMap<_, _>>(),
+                ),
+            ),
+        ])
+    }
+
+    unsafe fn format(schema: &ArrowSchema) -> &str {
+        CStr::from_ptr(schema.format).to_str().unwrap()
+    }
+
+    unsafe fn buffer<T>(array: &ArrowArray, index: usize, len: usize) -> &[T] {
+        slice::from_raw_parts(*array.buffers.add(index) as *const T, len)
+    }
+
+    #[test]
+    fn export_record_batch() {
+        let field = ArrowField::record_batch(&schema()).unwrap();
+        let mut column = Column::new(&field.data_type);
+        column
+            .append(
+                &field,
+                &record(1, Some("foo"), &[("b", &[1, 2]), ("a", &[])]),
+            )
+            .unwrap();
+        column.append(&field, &record(2, None, &[])).unwrap();
+        assert!(column.append(&field, &Value::Long(3)).is_err());
+
+        unsafe {
+            let mut schema = export_schema(&field);
+            assert_eq!(format(&schema), "+s");
+            assert_eq!(schema.n_children, 4);
+            let children = slice::from_raw_parts(schema.children, 4);
+            let formats = children
+                .iter()
+                .map(|&child| format(&*child))
+                .collect::<Vec<_>>();
+            assert_eq!(formats, vec!["l", "u", "i", "+m"]);
+            assert_eq!((*children[1]).flags, ARROW_FLAG_NULLABLE);
+            assert_eq!(format(&*(*children[2]).dictionary), "u");
+            release_schema(&mut schema);
+            assert!(schema.release.is_none());
+
+            let mut array = export_array(&field, column);
+            assert_eq!(
+                (array.length, array.null_count, array.n_children),
+                (2, 0, 4)
+            );
+            let children = slice::from_raw_parts(array.children, 4);
+            assert_eq!(buffer::<i64>(&*children[0], 1, 2), &[1, 2]);
+
+            let comment = &*children[1];
+            assert_eq!(comment.null_count, 1);
+            assert_eq!(buffer::<u8>(comment, 0, 1)[0] & 0b11, 0b01);
+            assert_eq!(buffer::<i32>(comment, 1, 3), &[0, 3, 3]);
+            assert_eq!(buffer::<u8>(comment, 2, 3), b"foo");
+
+            let suit = &*children[2];
+            assert_eq!(buffer::<i32>(suit, 1, 2), &[1, 1]);
+            assert_eq!((*suit.dictionary).length, 2);
+
+            let tags = &*children[3];
+            assert_eq!(buffer::<i32>(tags, 1, 3), &[0, 2, 2]);
+            let entries = &**tags.children;
+            let keys = &**entries.children;
+            assert_eq!(buffer::<u8>(keys, 2, 2), b"ab");
+            let items = &**(**entries.children.add(1)).children;
+            assert_eq!(buffer::<i32>(items, 1, 2), &[1, 2]);
+
+            release_array(&mut array);
+            assert!(array.release.is_none());
+        }
+    }
+
+    #[test]
+    fn read_column_append_error() {
+        let schema = Schema::parse_str(
+            r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "count", "type": ["null", "long"]}
+        ]}
+        "#,
+        )
+        .unwrap();
+        let record = |id: i64, count: Option<i64>| {
+            Value::Record(vec![
+                ("id".to_owned(), Value::Long(id)),
+                (
+                    "count".to_owned(),
+                    Value::Union(count.map(|n| Box::new(Value::Long(n)))),
+                ),
+            ])
+        };
+        let mut writer = avro_rs::Writer::new(&schema, Vec::new());
+        writer.append(record(1, None)).unwrap();
+        writer.append(record(2, Some(3))).unwrap();
+        writer.append(record(4, None)).unwrap();
+        writer.flush().unwrap();
+        let data = writer.into_inner();
+
+        // counts are int32 in the column, so that only null counts can be appended
+        let mut field = ArrowField::record_batch(&schema).unwrap();
+        if let ArrowType::Struct(ref mut fields) = field.data_type {
+            fields[1].data_type = ArrowType::Int32;
+        }
+
+        let mut handle = ReaderHandle {
+            reader: avro_rs::Reader::new(data.as_slice()).unwrap(),
+            options: Default::default(),
+            error: None,
+        };
+        let column = read_column(&mut handle, &field, 10).unwrap();
+        assert_eq!(column.len, 1);
+        match column.data {
+            ColumnData::Struct(ref children) => assert_eq!(children[0].len, 1),
+            _ => unreachable!(),
+        }
+        assert!(read_column(&mut handle, &field, 10).is_err());
+        assert_eq!(read_column(&mut handle, &field, 10).unwrap().len, 1);
+        assert_eq!(read_column(&mut handle, &field, 10).unwrap().len, 0);
+    }
+
+    #[test]
+    fn export_non_record() {
+        assert!(ArrowField::record_batch(&Schema::Long).is_err());
+    }
+
+    fn export(values: &[Value]) -> (ArrowSchema, ArrowArray) {
+        let field = ArrowField::record_batch(&schema()).unwrap();
+        let mut column = Column::new(&field.data_type);
+        for value in values {
+            column.append(&field, value).unwrap();
+        }
+        (export_schema(&field), export_array(&field, column))
+    }
+
+    #[test]
+    fn import_record_batch() {
+        let values = vec![
+            record(1, Some("foo"), &[("b", &[1, 2]), ("a", &[])]),
+            record(2, None, &[]),
+        ];
+        let (mut arrow_schema, mut array) = export(&values);
+
+        unsafe {
+            let imported = avro_values_from_arrow(&arrow_schema, &array, &schema()).unwrap();
+            assert_eq!(imported, values);
+
+            // missing fields take their default, or null when nullable
+            let with_defaults = Schema::parse_str(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "comment", "type": ["null", "string"]},
+                {"name": "suit", "type": "string"},
+                {"name": "tags", "type": {
+                    "type": "map", "values": {"type": "array", "items": "long"}
+                }},
+                {"name": "score", "type": "double", "default": 0.5},
+                {"name": "note", "type": ["null", "string"]}
+            ]}
+            "#).unwrap();
+            let imported = avro_values_from_arrow(&arrow_schema, &array, &with_defaults).unwrap();
+            match imported[1] {
+                Value::Record(ref fields) => {
+                    assert_eq!(fields[2].1, Value::String("hearts".to_owned()));
+                    assert_eq!(fields[4].1, Value::Double(0.5));
+                    assert_eq!(fields[5].1, Value::Union(None));
+                },
+                _ => panic!("not a record"),
+            }
+
+            release_schema(&mut arrow_schema);
+            release_array(&mut array);
+        }
+    }
+
+    #[test]
+    fn import_mismatch() {
+        let (mut arrow_schema, mut array) = export(&[record(1, None, &[])]);
+        let import = |json: &str| unsafe {
+            let schema = Schema::parse_str(json).unwrap();
+            avro_values_from_arrow(&arrow_schema, &array, &schema)
+                .unwrap_err()
+                .to_string()
+        };
+
+        let fields = r#"
+            {"name": "suit", "type": "string"},
+            {"name": "tags", "type": {"type": "map", "values": {"type": "array", "items": "int"}}}
+        "#;
+        let record = |id: &str, comment: &str| {
+            format!(
+                r#"{{"type": "record", "name": "Test", "fields": [
+                    {{"name": "id", "type": "{}"}}, {{"name": "comment", "type": {}}}, {}
+                ]}}"#,
+                id, comment, fields
+            )
+        };
+        assert_eq!(
+            import(&record("int", r#"["null", "string"]"#)),
+            "Arrow format `l` at `id` cannot be written as avro int"
+        );
+        assert_eq!(
+            import(&record("long", r#""string""#)),
+            "row 0: null at `comment` cannot be written as avro string"
+        );
+        assert_eq!(
+            import(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "id", "type": "long"}]}
+            "#),
+            "Arrow columns are not fields of the record: `comment`, `suit`, `tags`"
+        );
+
+        unsafe {
+            release_schema(&mut arrow_schema);
+            release_array(&mut array);
+        }
+    }
+
+    #[test]
+    fn import_out_of_bounds() {
+        let (mut arrow_schema, mut array) = export(&[record(1, Some("foo"), &[("a", &[1])])]);
+        let import = |array: &ArrowArray| unsafe {
+            avro_values_from_arrow(&arrow_schema, array, &schema())
+                .unwrap_err()
+                .to_string()
+        };
+
+        unsafe {
+            array.length = -1;
+            assert_eq!(
+                import(&array),
+                "malformed Arrow array: invalid length -1 or offset 0"
+            );
+            array.length = 2;
+            assert_eq!(
+                import(&array),
+                "malformed Arrow array: child shorter than its parent"
+            );
+            array.length = 1;
+
+            let child = |index: usize| &mut **array.children.add(index);
+            let buffer =
+                |array: &mut ArrowArray, index: usize| *array.buffers.add(index) as *mut i32;
+
+            let id = child(0);
+            let data = *id.buffers.add(1);
+            *id.buffers.add(1) = ptr::null();
+            assert_eq!(
+                import(&array),
+                "malformed Arrow array at `id`: null buffer 1"
+            );
+            *child(0).buffers.add(1) = data;
+
+            *buffer(child(1), 1) = -1;
+            assert_eq!(
+                import(&array),
+                "row 0: Arrow offsets -1..3 at `comment` are out of bounds"
+            );
+            *buffer(child(1), 1) = 0;
+
+            *buffer(child(2), 1) = 2;
+            assert_eq!(
+                import(&array),
+                "row 0: dictionary index 2 at `suit` is out of bounds"
+            );
+            *buffer(child(2), 1) = 1;
+
+            *buffer(child(3), 1).add(1) = 5;
+            assert_eq!(
+                import(&array),
+                "row 0: Arrow offsets 0..5 at `tags` are out of bounds"
+            );
+            *buffer(child(3), 1).add(1) = 1;
+
+            assert!(avro_values_from_arrow(&arrow_schema, &array, &schema()).is_ok());
+            release_schema(&mut arrow_schema);
+            release_array(&mut array);
+        }
+    }
+
+    #[test]
+    fn offsets_overflow() {
+        assert_eq!(offset(i32::MAX as usize).unwrap(), i32::MAX);
+        let err = offset(i32::MAX as usize + 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "offset 2147483648 does not fit in the 32-bit offsets of Arrow arrays"
+        );
+    }
+}
@@ -1,88 +1,526 @@
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 
-use failure::{err_msg, Error};
+use failure::{err_msg, Error, Fail};
+use num_traits::ToPrimitive;
 
 use avro_rs::schema::{RecordField, Schema};
-use avro_rs::types::{ToAvro, Value};
+use avro_rs::types::Value;
+use options::{AvroEnumMode, AvroRecordMode, ConversionOptions};
+use path::{value_kind, Path, PathSegment};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+use serde_json::Value as JsonValue;
+use serde_pickle;
 use serde_pickle::value::HashableValue;
 use serde_pickle::value::Value as PickleValue;
 
-pub fn avro_value_from_pickle(schema: &Schema, value: PickleValue) -> Result<Value, Error> {
+/// Check a value against a schema, falling back to schema resolution for values which are only
+/// compatible with it (e.g. an `int` given for a `long` field).
+pub fn conform_value(schema: &Schema, value: Value) -> Result<Value, Error> {
+    if value.validate(schema) {
+        Ok(value)
+    } else {
+        value.resolve(schema)
+    }
+}
+
+/// Integer as a float, if it can be represented exactly.
+fn exact_float(n: i64) -> Result<f32, Error> {
+    let x = n as f32;
+    if x as i128 == i128::from(n) {
+        Ok(x)
+    } else {
+        Err(err_msg(format!("{} cannot be represented exactly as float", n)))
+    }
+}
+
+/// Integer as a double, if it can be represented exactly.
+fn exact_double(n: i64) -> Result<f64, Error> {
+    let x = n as f64;
+    if x as i128 == i128::from(n) {
+        Ok(x)
+    } else {
+        Err(err_msg(format!("{} cannot be represented exactly as double", n)))
+    }
+}
+
+/// Check a value against a schema, only converting numbers to other numeric types without losing
+/// precision: `int` to `long` or `double` and `float` to `double` always, and `long` to `int`,
+/// `int` or `long` to `float` and `long` to `double` when the number is represented exactly (e.g.
+/// up to 2^24 in absolute value for `float`). Any other mismatch is an error.
+pub fn coerce_value(schema: &Schema, value: Value) -> Result<Value, Error> {
+    if value.validate(schema) {
+        return Ok(value)
+    }
+
+    let mismatch = |value: &Value| {
+        err_msg(format!(
+            "expected {}, got {}",
+            schema_name(schema),
+            value_kind(value)
+        ))
+    };
+    match (schema, value) {
+        (Schema::Int, Value::Long(n)) => i32::try_from(n)
+            .map(Value::Int)
+            .map_err(|_| err_msg(format!("{} is out of range for int", n))),
+        (Schema::Long, Value::Int(n)) => Ok(Value::Long(i64::from(n))),
+        (Schema::Float, Value::Int(n)) => exact_float(i64::from(n)).map(Value::Float),
+        (Schema::Float, Value::Long(n)) => exact_float(n).map(Value::Float),
+        (Schema::Double, Value::Int(n)) => Ok(Value::Double(f64::from(n))),
+        (Schema::Double, Value::Long(n)) => exact_double(n).map(Value::Double),
+        (Schema::Double, Value::Float(x)) => Ok(Value::Double(f64::from(x))),
+        (Schema::Union(_), Value::Null) | (Schema::Union(_), Value::Union(None)) => {
+            Ok(Value::Union(None))
+        },
+        (Schema::Union(inner), Value::Union(Some(value))) => {
+            Ok(Value::Union(Some(Box::new(coerce_value(inner, *value)?))))
+        },
+        (Schema::Union(inner), value) => {
+            Ok(Value::Union(Some(Box::new(coerce_value(inner, value)?))))
+        },
+        (Schema::Array(inner), Value::Array(items)) => items
+            .into_iter()
+            .map(|item| coerce_value(inner, item))
+            .collect::<Result<_, _>>()
+            .map(Value::Array),
+        (Schema::Map(inner), Value::Map(items)) => items
+            .into_iter()
+            .map(|(key, value)| Ok((key, coerce_value(inner, value)?)))
+            .collect::<Result<_, Error>>()
+            .map(Value::Map),
+        (Schema::Record { fields, .. }, Value::Record(values)) => {
+            if values.len() != fields.len()
+                || fields
+                    .iter()
+                    .zip(&values)
+                    .any(|(field, value)| field.name != value.0)
+            {
+                return Err(mismatch(&Value::Record(values)))
+            }
+            fields
+                .iter()
+                .zip(values)
+                .map(|(field, (name, value))| {
+                    coerce_value(&field.schema, value)
+                        .map(|value| (name, value))
+                        .map_err(|err| err_msg(format!("field `{}`: {}", field.name, err)))
+                })
+                .collect::<Result<_, _>>()
+                .map(Value::Record)
+        },
+        (_, value) => Err(mismatch(&value)),
+    }
+}
+
+/// Same as `conform_value`, but only clones values which have to be resolved.
+pub fn conform_value_ref<'a>(schema: &Schema, value: &'a Value) -> Result<Cow<'a, Value>, Error> {
+    if value.validate(schema) {
+        Ok(Cow::Borrowed(value))
+    } else {
+        value.clone().resolve(schema).map(Cow::Owned)
+    }
+}
+
+/// Type name of a schema: the primitive type name, or the full name of named types.
+pub fn schema_name(schema: &Schema) -> String {
+    match *schema {
+        Schema::Null => "null".to_owned(),
+        Schema::Boolean => "boolean".to_owned(),
+        Schema::Int => "int".to_owned(),
+        Schema::Long => "long".to_owned(),
+        Schema::Float => "float".to_owned(),
+        Schema::Double => "double".to_owned(),
+        Schema::Bytes => "bytes".to_owned(),
+        Schema::String => "string".to_owned(),
+        Schema::Array(_) => "array".to_owned(),
+        Schema::Map(_) => "map".to_owned(),
+        Schema::Union(_) => "union".to_owned(),
+        Schema::Record { ref name, .. }
+        | Schema::Enum { ref name, .. }
+        | Schema::Fixed { ref name, .. } => name.fullname(None),
+    }
+}
+
+/// Whether a type name designates a schema. Named types match both their full and short names.
+pub fn schema_matches_name(schema: &Schema, name: &str) -> bool {
     match *schema {
+        Schema::Record { name: ref n, .. }
+        | Schema::Enum { name: ref n, .. }
+        | Schema::Fixed { name: ref n, .. } => n.name == name || n.fullname(None) == name,
+        _ => schema_name(schema) == name,
+    }
+}
+
+/// Convert the JSON default of a record field into a value of the schema of the field.
+///
+/// See [default values](https://avro.apache.org/docs/current/spec.html#schema_record) in the
+/// avro specification.
+pub fn avro_value_from_json_default(schema: &Schema, default: &JsonValue) -> Result<Value, Error> {
+    let mismatch = || err_msg(format!("default {} is not a valid {}", default, schema_name(schema)));
+    let bytes = |s: &str| {
+        s.chars()
+            .map(|c| if (c as u32) < 256 { Some(c as u8) } else { None })
+            .collect::<Option<Vec<_>>>()
+    };
+
+    match (schema, default) {
+        (Schema::Null, JsonValue::Null) => Ok(Value::Null),
+        (Schema::Boolean, JsonValue::Bool(b)) => Ok(Value::Boolean(*b)),
+        (Schema::Int, JsonValue::Number(n)) => n
+            .as_i64()
+            .filter(|n| *n >= i64::from(i32::MIN) && *n <= i64::from(i32::MAX))
+            .map(|n| Value::Int(n as i32))
+            .ok_or_else(mismatch),
+        (Schema::Long, JsonValue::Number(n)) => {
+            n.as_i64().map(Value::Long).ok_or_else(mismatch)
+        },
+        (Schema::Float, JsonValue::Number(n)) => n
+            .as_f64()
+            .map(|x| Value::Float(x as f32))
+            .ok_or_else(mismatch),
+        (Schema::Double, JsonValue::Number(n)) => {
+            n.as_f64().map(Value::Double).ok_or_else(mismatch)
+        },
+        (Schema::Bytes, JsonValue::String(s)) => {
+            bytes(s).map(Value::Bytes).ok_or_else(mismatch)
+        },
+        (Schema::String, JsonValue::String(s)) => Ok(Value::String(s.clone())),
+        (Schema::Fixed { size, .. }, JsonValue::String(s)) => bytes(s)
+            .filter(|bytes| bytes.len() == *size)
+            .map(|bytes| Value::Fixed(*size, bytes))
+            .ok_or_else(mismatch),
+        (Schema::Enum { symbols, .. }, JsonValue::String(s)) => symbols
+            .iter()
+            .position(|symbol| symbol == s)
+            .map(|index| Value::Enum(index as i32, s.clone()))
+            .ok_or_else(mismatch),
+        (Schema::Union(_), JsonValue::Null) => Ok(Value::Union(None)),
+        (Schema::Union(inner), default) => Ok(Value::Union(Some(Box::new(
+            avro_value_from_json_default(inner, default)?,
+        )))),
+        (Schema::Array(inner), JsonValue::Array(items)) => items
+            .iter()
+            .map(|item| avro_value_from_json_default(inner, item))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        (Schema::Map(inner), JsonValue::Object(items)) => items
+            .iter()
+            .map(|(key, value)| {
+                avro_value_from_json_default(inner, value).map(|value| (key.clone(), value))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()
+            .map(Value::Map),
+        (Schema::Record { fields, .. }, JsonValue::Object(items)) => fields
+            .iter()
+            .map(|field| {
+                let value = match (items.get(&field.name), field.default.as_ref()) {
+                    (Some(value), _) | (None, Some(value)) => {
+                        avro_value_from_json_default(&field.schema, value)?
+                    },
+                    (None, None) => {
+                        return Err(err_msg(format!("missing field {} in default", field.name)))
+                    },
+                };
+                Ok((field.name.clone(), value))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Record),
+        _ => Err(mismatch()),
+    }
+}
+
+/// Build a record value out of the defaults of its fields. Nullable fields without a default
+/// are set to null, and fields which are neither nullable nor have a default are reported.
+pub fn avro_record_from_defaults(schema: &Schema) -> Result<Value, Error> {
+    let fields = match *schema {
+        Schema::Record { ref fields, .. } => fields,
+        _ => return Err(err_msg("schema is not a record")),
+    };
+
+    let mut missing = Vec::new();
+    let mut values = Vec::with_capacity(fields.len());
+    for field in fields {
+        let value = match (field.default.as_ref(), &field.schema) {
+            (Some(default), schema) => avro_value_from_json_default(schema, default)
+                .map_err(|err| err_msg(format!("field `{}`: {}", field.name, err)))?,
+            (None, &Schema::Union(_)) => Value::Union(None),
+            (None, _) => {
+                missing.push(field.name.as_str());
+                continue
+            },
+        };
+        values.push((field.name.clone(), value));
+    }
+
+    if missing.is_empty() {
+        Ok(Value::Record(values))
+    } else {
+        Err(err_msg(format!(
+            "no default for required fields: {}",
+            missing.join(", ")
+        )))
+    }
+}
+
+/// Error converting a pickled value, locating the offending value in the converted one.
+#[derive(Debug)]
+pub struct PickleConversionError {
+    pub path: Path,
+    /// Name of the expected schema.
+    pub expected: String,
+    pub python_type: &'static str,
+    /// What is wrong with the value, beyond its Python type.
+    pub reason: Option<String>,
+}
+
+impl PickleConversionError {
+    /// Message of the error, naming the type found (e.g. `Python str`).
+    pub fn describe(&self, found: &str) -> String {
+        let mut message = format!("expected {}", self.expected);
+        if !self.path.0.is_empty() {
+            message.push_str(&format!(" at `{}`", self.path));
+        }
+        message.push_str(&format!(", got {}", found));
+        if let Some(ref reason) = self.reason {
+            message.push_str(&format!(": {}", reason));
+        }
+        message
+    }
+}
+
+impl fmt::Display for PickleConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(&format!("Python {}", self.python_type)))
+    }
+}
+
+impl Fail for PickleConversionError {}
+
+/// A pickled value of a Python type which cannot be converted to the expected schema.
+#[derive(Debug)]
+struct TypeMismatch;
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "type mismatch")
+    }
+}
+
+impl Fail for TypeMismatch {}
+
+/// Locate the conversion error of a nested value under the segment leading to it.
+fn nested(err: Error, segment: PathSegment) -> Error {
+    match err.downcast::<PickleConversionError>() {
+        Ok(mut err) => {
+            err.path.0.insert(0, segment);
+            err.into()
+        },
+        Err(err) => err,
+    }
+}
+
+/// Convert a pickled value to an avro value of a schema, with the default conversion options.
+#[allow(dead_code)]
+pub fn avro_value_from_pickle(schema: &Schema, value: PickleValue) -> Result<Value, Error> {
+    avro_value_from_pickle_with(schema, value, &ConversionOptions::default())
+}
+
+/// Convert a pickled value to an avro value of a schema.
+pub fn avro_value_from_pickle_with(
+    schema: &Schema,
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
+    let python_type = pickle_type_name(&value);
+    let result = match *schema {
         Schema::Null => from_null(&value),
         Schema::Boolean => from_boolean(&value),
         Schema::Int => from_int(&value),
         Schema::Long => from_long(&value),
-        Schema::Float => from_float(&value),
-        Schema::Double => from_double(&value),
-        Schema::Bytes => from_bytes(value),
-        Schema::String => from_string(value),
+        Schema::Float => from_float(&value, options),
+        Schema::Double => from_double(&value, options),
+        Schema::Bytes => from_bytes(value, options),
+        Schema::String => from_string(value, options),
         Schema::Fixed { size, .. } => from_fixed(size, value),
-        Schema::Array(ref inner) => from_array(inner, value),
-        Schema::Map(ref inner) => from_map(inner, value),
-        Schema::Union(ref inner) => from_union(inner, value),
-        Schema::Record { ref fields, .. } => from_record(fields, value),
-        Schema::Enum { ref symbols, .. } => from_enum(symbols, value),
-    }
+        Schema::Array(ref inner) => from_array(inner, value, options),
+        Schema::Map(ref inner) => from_map(inner, value, options),
+        Schema::Union(ref inner) => from_union(inner, value, options),
+        Schema::Record { ref fields, .. } => from_record(fields, value, options),
+        Schema::Enum { ref symbols, .. } => from_enum(symbols, value, options),
+    };
+
+    result.map_err(|err| match err.downcast::<PickleConversionError>() {
+        Ok(err) => err.into(),
+        Err(err) => {
+            let reason = match err.downcast::<TypeMismatch>() {
+                Ok(_) => None,
+                Err(err) => Some(err.to_string()),
+            };
+            PickleConversionError {
+                path: Path::default(),
+                expected: schema_name(schema),
+                python_type,
+                reason,
+            }
+            .into()
+        },
+    })
+}
+
+/// Convert a pickled list of values of a schema, each of them separately. The error of a
+/// value gives its path from the list, starting with its index.
+pub fn avro_values_from_pickle(
+    schema: &Schema,
+    values: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Vec<Result<Value, Error>>, Error> {
+    let values = match values {
+        PickleValue::List(values) | PickleValue::Tuple(values) => values,
+        values => {
+            return Err(err_msg(format!(
+                "expected a list of values, got Python {}",
+                pickle_type_name(&values)
+            )))
+        },
+    };
+    Ok(values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            avro_value_from_pickle_with(schema, value, options)
+                .map_err(|err| nested(err, PathSegment::Index(i)))
+        })
+        .collect())
 }
 
 fn from_null(value: &PickleValue) -> Result<Value, Error> {
     match value {
         PickleValue::None => Ok(Value::Null),
-        _ => Err(err_msg("not a null")),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
 fn from_boolean(value: &PickleValue) -> Result<Value, Error> {
     match value {
         PickleValue::Bool(b) => Ok(Value::Boolean(*b)),
-        _ => Err(err_msg("not a bool")),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
 fn from_int(value: &PickleValue) -> Result<Value, Error> {
-    match value {
-        PickleValue::I64(n) => Ok(Value::Int(*n as i32)),
-        _ => Err(err_msg("not an int")),
-    }
+    let n = match value {
+        PickleValue::I64(n) => Some(*n),
+        PickleValue::Int(n) => n.to_i64(),
+        _ => return Err(TypeMismatch.into()),
+    };
+    n.and_then(|n| n.to_i32())
+        .map(Value::Int)
+        .ok_or_else(|| err_msg(format!("{} is out of range for an int", value_repr(value))))
 }
 
 fn from_long(value: &PickleValue) -> Result<Value, Error> {
-    match value {
-        PickleValue::I64(n) => Ok(Value::Long(*n)),
-        _ => Err(err_msg("not a long")),
+    let n = match value {
+        PickleValue::I64(n) => Some(*n),
+        PickleValue::Int(n) => n.to_i64(),
+        _ => return Err(TypeMismatch.into()),
+    };
+    n.map(Value::Long)
+        .ok_or_else(|| err_msg(format!("{} is out of range for a long", value_repr(value))))
+}
+
+/// A pickled int or float as a double, `None` for ints too large to be represented.
+fn pickle_double(value: &PickleValue) -> Option<f64> {
+    match *value {
+        PickleValue::F64(x) => Some(x),
+        PickleValue::I64(n) => Some(n as f64),
+        PickleValue::Int(ref n) => n.to_f64().filter(|x| x.is_finite()),
+        _ => None,
     }
 }
 
-fn from_float(value: &PickleValue) -> Result<Value, Error> {
+fn from_float(value: &PickleValue, options: &ConversionOptions) -> Result<Value, Error> {
     match value {
-        PickleValue::F64(x) => Ok(Value::Float(*x as f32)),
-        _ => Err(err_msg("not a float")),
+        PickleValue::I64(_) | PickleValue::Int(_) if options.strict => Err(TypeMismatch.into()),
+        PickleValue::F64(_) | PickleValue::I64(_) | PickleValue::Int(_) => {
+            match pickle_double(value) {
+                // infinities and NaN are kept, finite values must fit in a float
+                Some(x) if !x.is_finite() || x.abs() <= f64::from(f32::MAX) => {
+                    Ok(Value::Float(x as f32))
+                },
+                _ => Err(err_msg(format!(
+                    "{} is out of range for a float",
+                    value_repr(value)
+                ))),
+            }
+        },
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_double(value: &PickleValue) -> Result<Value, Error> {
+fn from_double(value: &PickleValue, options: &ConversionOptions) -> Result<Value, Error> {
     match value {
-        PickleValue::F64(x) => Ok(Value::Double(*x)),
-        _ => Err(err_msg("not a double")),
+        PickleValue::I64(_) | PickleValue::Int(_) if options.strict => Err(TypeMismatch.into()),
+        PickleValue::F64(_) | PickleValue::I64(_) | PickleValue::Int(_) => pickle_double(value)
+            .map(Value::Double)
+            .ok_or_else(|| err_msg(format!("{} is out of range for a double", value_repr(value)))),
+        _ => Err(TypeMismatch.into()),
+    }
+}
+
+/// Short representation of a numeric pickled value, for error messages.
+fn value_repr(value: &PickleValue) -> String {
+    match *value {
+        PickleValue::I64(n) => n.to_string(),
+        PickleValue::Int(ref n) => n.to_string(),
+        PickleValue::F64(x) => python_float_str(x),
+        _ => pickle_type_name(value).to_owned(),
+    }
+}
+
+/// Format a float like Python's `str`: the shortest representation reading back to the same
+/// float, in scientific notation with a signed exponent of at least two digits below 1e-4 and
+/// from 1e16 on, and always with a fractional part otherwise.
+fn python_float_str(x: f64) -> String {
+    if x.is_nan() {
+        return "nan".to_owned()
+    }
+    if x.is_infinite() {
+        return if x > 0.0 { "inf" } else { "-inf" }.to_owned()
+    }
+    let scientific = format!("{:e}", x);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+    if x == 0.0 || (-4..16).contains(&exponent) {
+        let fixed = x.to_string();
+        if fixed.contains('.') {
+            fixed
+        } else {
+            fixed + ".0"
+        }
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
     }
 }
 
-fn from_bytes(value: PickleValue) -> Result<Value, Error> {
+fn from_bytes(value: PickleValue, options: &ConversionOptions) -> Result<Value, Error> {
     match value {
         PickleValue::Bytes(bytes) => Ok(Value::Bytes(bytes)),
-        PickleValue::String(s) => Ok(Value::Bytes(s.into_bytes())),
-        _ => Err(err_msg("not a bytes")),
+        PickleValue::String(s) if options.str_bytes_interchange => Ok(Value::Bytes(s.into_bytes())),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_string(value: PickleValue) -> Result<Value, Error> {
+fn from_string(value: PickleValue, options: &ConversionOptions) -> Result<Value, Error> {
     match value {
         PickleValue::String(s) => Ok(Value::String(s)),
-        PickleValue::Bytes(bytes) => String::from_utf8(bytes)
+        PickleValue::Bytes(bytes) if options.str_bytes_interchange => String::from_utf8(bytes)
             .map_err(|_| err_msg("not a valid utf-8 string"))
             .map(Value::String),
-        _ => Err(err_msg("not a string")),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
@@ -92,132 +530,308 @@ fn from_fixed(size: usize, value: PickleValue) -> Result<Value, Error> {
             if size == bytes.len() {
                 Ok(Value::Fixed(size, bytes))
             } else {
-                Err(err_msg("fixed size does not match"))
+                Err(err_msg(format!(
+                    "{} bytes for a fixed of size {}",
+                    bytes.len(),
+                    size
+                )))
             }
         },
-        _ => Err(err_msg("not a fixed")),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_array(schema: &Schema, value: PickleValue) -> Result<Value, Error> {
+fn from_array(
+    schema: &Schema,
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
     match value {
-        PickleValue::List(values) | PickleValue::Tuple(values) => Ok(Value::Array(
-            values
-                .into_iter()
-                .map(|value| avro_value_from_pickle(schema, value))
-                .collect::<Result<Vec<_>, _>>()?,
-        )),
-        PickleValue::Set(values) | PickleValue::FrozenSet(values) => Ok(Value::Array(
-            values
-                .into_iter()
-                .map(|value| avro_value_from_pickle(schema, value.into_value()))
-                .collect::<Result<Vec<_>, _>>()?,
-        )),
-        _ => Err(err_msg("not an array")),
+        PickleValue::List(values) => from_items(schema, values, options),
+        PickleValue::Tuple(values) if !options.strict => from_items(schema, values, options),
+        PickleValue::Set(values) | PickleValue::FrozenSet(values) if !options.strict => {
+            let values = values.into_iter().map(HashableValue::into_value).collect();
+            from_items(schema, values, options)
+        },
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_map(schema: &Schema, value: PickleValue) -> Result<Value, Error> {
+fn from_items(
+    schema: &Schema,
+    values: Vec<PickleValue>,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
+    Ok(Value::Array(
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                avro_value_from_pickle_with(schema, value, options)
+                    .map_err(|err| nested(err, PathSegment::Index(i)))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    ))
+}
+
+fn from_map(
+    schema: &Schema,
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
     match value {
         PickleValue::Dict(values) => Ok(Value::Map(
             values
                 .into_iter()
                 .map(|(key, value)| {
-                    if let HashableValue::String(key) = key {
-                        let value = avro_value_from_pickle(schema, value)?;
-                        Ok((key, value))
-                    } else {
-                        Err(err_msg("map key should be string"))
-                    }
+                    let key = map_key(key, options)?;
+                    let value = avro_value_from_pickle_with(schema, value, options)
+                        .map_err(|err| nested(err, PathSegment::Key(key.clone())))?;
+                    Ok((key, value))
                 })
-                .collect::<Result<HashMap<_, _>, _>>()?,
+                .collect::<Result<HashMap<_, _>, Error>>()?,
         )),
-        _ => Err(err_msg("not a map")),
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_union(schema: &Schema, value: PickleValue) -> Result<Value, Error> {
+/// Key of a map entry, stringifying keys which are not str like Python's `str` if the options
+/// allow it.
+fn map_key(key: HashableValue, options: &ConversionOptions) -> Result<String, Error> {
+    let key = match key {
+        HashableValue::String(key) => return Ok(key),
+        key if !options.stringify_map_keys => key,
+        HashableValue::None => return Ok("None".to_owned()),
+        HashableValue::Bool(b) => return Ok(if b { "True" } else { "False" }.to_owned()),
+        HashableValue::I64(n) => return Ok(n.to_string()),
+        HashableValue::Int(n) => return Ok(n.to_string()),
+        HashableValue::F64(x) => return Ok(python_float_str(x)),
+        HashableValue::Bytes(bytes) => {
+            return String::from_utf8(bytes)
+                .map_err(|_| err_msg("map key of Python type bytes is not valid utf-8"))
+        },
+        key => key,
+    };
+    Err(err_msg(format!(
+        "map key of Python type {} is not a str",
+        pickle_type_name(&key.into_value())
+    )))
+}
+
+/// Name of the Python type of a pickled value, for error messages.
+pub fn pickle_type_name(value: &PickleValue) -> &'static str {
+    match *value {
+        PickleValue::None => "NoneType",
+        PickleValue::Bool(_) => "bool",
+        PickleValue::I64(_) | PickleValue::Int(_) => "int",
+        PickleValue::F64(_) => "float",
+        PickleValue::Bytes(_) => "bytes",
+        PickleValue::String(_) => "str",
+        PickleValue::List(_) => "list",
+        PickleValue::Tuple(_) => "tuple",
+        PickleValue::Set(_) => "set",
+        PickleValue::FrozenSet(_) => "frozenset",
+        PickleValue::Dict(_) => "dict",
+    }
+}
+
+/// Name of the field a dict key stands for. Bytes keys are only accepted if not strict.
+fn dict_key(key: &HashableValue, strict: bool) -> Option<&str> {
+    match *key {
+        HashableValue::String(ref key) => Some(key),
+        HashableValue::Bytes(ref key) if !strict => ::std::str::from_utf8(key).ok(),
+        _ => None,
+    }
+}
+
+/// Whether a field may be missing from a dict converted to a record.
+fn field_optional(field: &RecordField, options: &ConversionOptions) -> bool {
+    match field.schema {
+        _ if field.default.is_some() => true,
+        Schema::Union(_) => options.missing_nullable_as_null,
+        _ => false,
+    }
+}
+
+/// Convert a pickled value to a `["null", T]` union, the only unions avro-rs supports: `None`
+/// is the null branch, and any other value is converted to `T`. There is no branch to choose by
+/// the Python type of the value, as avro-rs 0.4 cannot parse unions with several non-null
+/// branches.
+fn from_union(
+    schema: &Schema,
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
     match value {
         PickleValue::None => Ok(Value::Union(None)),
-        value => Ok(Value::Union(Some(Box::new(avro_value_from_pickle(
-            schema, value,
+        value => Ok(Value::Union(Some(Box::new(avro_value_from_pickle_with(
+            schema, value, options,
         )?)))),
     }
 }
 
-fn from_enum(symbols: &[String], value: PickleValue) -> Result<Value, Error> {
+fn from_enum(
+    symbols: &[String],
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
+    let accepts = |mode| mode == options.enum_mode || !options.strict;
     match value {
-        PickleValue::String(s) => Ok(Value::Enum(
+        PickleValue::String(s) if accepts(AvroEnumMode::Symbol) => Ok(Value::Enum(
             symbols
                 .iter()
                 .position(|ref item| item == &&s)
-                .ok_or_else(|| err_msg("unsupported enum value"))? as i32,
+                .ok_or_else(|| err_msg(format!("`{}` is not a symbol of the enum", s)))?
+                as i32,
             s,
         )),
-        _ => Err(err_msg("not an enum")),
+        PickleValue::I64(index) if accepts(AvroEnumMode::Index) => {
+            if index < 0 || index as usize >= symbols.len() {
+                return Err(err_msg(format!(
+                    "{} is not the index of one of the {} symbols of the enum",
+                    index,
+                    symbols.len()
+                )))
+            }
+            Ok(Value::Enum(index as i32, symbols[index as usize].clone()))
+        },
+        _ => Err(TypeMismatch.into()),
     }
 }
 
-fn from_record(schema_fields: &[RecordField], value: PickleValue) -> Result<Value, Error> {
-    match value {
-        PickleValue::Dict(mut fields) => Ok(Value::Record(
-            schema_fields
-                .iter()
-                .map(|field| {
-                    let value = match fields.remove(&HashableValue::String(field.name.clone())) {
-                        Some(value) => avro_value_from_pickle(&field.schema, value),
-                        None => match fields
-                            .remove(&HashableValue::Bytes(field.name.clone().into_bytes()))
-                        {
-                            Some(value) => avro_value_from_pickle(&field.schema, value),
-                            None => match field.default {
-                                Some(ref value) => Ok(value.clone().avro()),
-                                None => {
-                                    Err(err_msg(format!("missing field {} in record", field.name)))
-                                },
-                            },
-                        },
-                    };
-
-                    value.map(|value| (field.name.clone(), value))
-                })
-                .collect::<Result<Vec<_>, _>>()?,
-        )),
-        _ => Err(err_msg("not a record")),
+fn from_record(
+    schema_fields: &[RecordField],
+    value: PickleValue,
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
+    let mut fields = match value {
+        PickleValue::Dict(fields) => fields,
+        _ => return Err(TypeMismatch.into()),
+    };
+
+    let record = schema_fields
+        .iter()
+        .map(|field| {
+            let mut value = fields.remove(&HashableValue::String(field.name.clone()));
+            if value.is_none() && !options.strict {
+                value = fields.remove(&HashableValue::Bytes(field.name.clone().into_bytes()));
+            }
+            let value = match value {
+                Some(value) => avro_value_from_pickle_with(&field.schema, value, options)
+                    .map_err(|err| nested(err, PathSegment::Field(field.name.clone()))),
+                None => match field.default {
+                    Some(ref value) => avro_value_from_json_default(&field.schema, value)
+                        .map_err(|err| {
+                            err_msg(format!("default of field `{}`: {}", field.name, err))
+                        }),
+                    None if field_optional(field, options) => Ok(Value::Union(None)),
+                    None => Err(err_msg(format!("missing field `{}`", field.name))),
+                },
+            };
+
+            value.map(|value| (field.name.clone(), value))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if options.reject_extra_keys && !fields.is_empty() {
+        let keys = fields
+            .keys()
+            .map(|key| match dict_key(key, false) {
+                Some(key) => format!("`{}`", key),
+                None => {
+                    let key = key.clone().into_value();
+                    format!("a key of Python type {}", pickle_type_name(&key))
+                },
+            })
+            .collect::<Vec<_>>();
+        return Err(err_msg(format!("not fields of the record: {}", keys.join(", "))))
     }
+
+    Ok(Value::Record(record))
 }
 
-pub fn pickle_value_from_avro(value: Value) -> PickleValue {
-    match value {
-        Value::Null => PickleValue::None,
-        Value::Boolean(b) => PickleValue::Bool(b),
-        Value::Int(n) => PickleValue::I64(i64::from(n)),
-        Value::Long(n) => PickleValue::I64(n),
-        Value::Float(x) => PickleValue::F64(f64::from(x)),
-        Value::Double(x) => PickleValue::F64(x),
-        Value::Bytes(bytes) | Value::Fixed(_, bytes) => PickleValue::Bytes(bytes),
-        Value::String(s) => PickleValue::String(s),
-        Value::Array(values) => {
-            PickleValue::List(values.into_iter().map(pickle_value_from_avro).collect())
-        },
-        Value::Map(values) => PickleValue::Dict(
-            values
-                .into_iter()
-                .map(|(key, value)| (HashableValue::String(key), pickle_value_from_avro(value)))
-                .collect::<BTreeMap<_, _>>(),
-        ),
-        Value::Union(None) => PickleValue::None,
-        Value::Union(Some(value)) => pickle_value_from_avro(*value),
-        Value::Record(fields) => PickleValue::Dict(
-            fields
-                .into_iter()
-                .map(|(key, value)| (HashableValue::String(key), pickle_value_from_avro(value)))
-                .collect::<BTreeMap<_, _>>(),
-        ),
-        Value::Enum(_, repr) => PickleValue::String(repr),
+/// An avro value serialized as a pickled value, with records and enums represented as chosen
+/// by conversion options.
+pub struct PickleAvro<'a> {
+    pub value: &'a Value,
+    pub options: &'a ConversionOptions,
+}
+
+impl<'a> PickleAvro<'a> {
+    fn nested<'b>(&'b self, value: &'b Value) -> PickleAvro<'b> {
+        PickleAvro {
+            value,
+            options: self.options,
+        }
+    }
+}
+
+impl<'a> Serialize for PickleAvro<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self.value {
+            Value::Null | Value::Union(None) => serializer.serialize_none(),
+            Value::Boolean(b) => serializer.serialize_bool(b),
+            Value::Int(n) => serializer.serialize_i64(i64::from(n)),
+            Value::Long(n) => serializer.serialize_i64(n),
+            Value::Float(x) => serializer.serialize_f64(f64::from(x)),
+            Value::Double(x) => serializer.serialize_f64(x),
+            Value::Bytes(ref bytes) | Value::Fixed(_, ref bytes) => {
+                serializer.serialize_bytes(bytes)
+            },
+            Value::String(ref s) => serializer.serialize_str(s),
+            Value::Array(ref values) => {
+                serializer.collect_seq(values.iter().map(|value| self.nested(value)))
+            },
+            Value::Map(ref values) => {
+                // entries are sorted for the same value to always give the same pickle
+                let mut entries = values.iter().collect::<Vec<_>>();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                serializer.collect_map(
+                    entries
+                        .into_iter()
+                        .map(|(key, value)| (key, self.nested(value))),
+                )
+            },
+            Value::Union(Some(ref value)) => self.nested(value).serialize(serializer),
+            Value::Record(ref fields) => match self.options.record_mode {
+                AvroRecordMode::Dict => serializer.collect_map(
+                    fields.iter().map(|(name, value)| (name, self.nested(value))),
+                ),
+                AvroRecordMode::Tuple => {
+                    let mut tuple = serializer.serialize_tuple(fields.len())?;
+                    for (_, value) in fields {
+                        tuple.serialize_element(&self.nested(value))?;
+                    }
+                    tuple.end()
+                },
+            },
+            Value::Enum(index, ref symbol) => match self.options.enum_mode {
+                AvroEnumMode::Symbol => serializer.serialize_str(symbol),
+                AvroEnumMode::Index => serializer.serialize_i64(i64::from(index)),
+            },
+        }
     }
 }
 
+/// Pickle an avro value, with the protocol and representations of the conversion options.
+pub fn pickle_from_avro(value: &Value, options: &ConversionOptions) -> Result<Vec<u8>, Error> {
+    let pickle = PickleAvro { value, options };
+    Ok(serde_pickle::to_vec(&pickle, options.use_proto_3()?)?)
+}
+
+/// Pickle a list of avro values, with the protocol and representations of the conversion
+/// options.
+pub fn pickle_list_from_avro(
+    values: &[Value],
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, Error> {
+    let pickles = values
+        .iter()
+        .map(|value| PickleAvro { value, options })
+        .collect::<Vec<_>>();
+    Ok(serde_pickle::to_vec(&pickles, options.use_proto_3()?)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -250,4 +864,416 @@ mod tests {
             assert!(false);
         }
     }
+
+    #[test]
+    fn coerce_numbers() {
+        assert_eq!(coerce_value(&Schema::Long, Value::Int(42)).unwrap(), Value::Long(42));
+        assert_eq!(coerce_value(&Schema::Int, Value::Long(42)).unwrap(), Value::Int(42));
+        assert_eq!(
+            coerce_value(&Schema::Double, Value::Float(0.5)).unwrap(),
+            Value::Double(0.5)
+        );
+        assert_eq!(
+            coerce_value(&Schema::Int, Value::Long(1 << 40)).unwrap_err().to_string(),
+            "1099511627776 is out of range for int"
+        );
+        assert_eq!(
+            coerce_value(&Schema::Long, Value::Boolean(true)).unwrap_err().to_string(),
+            "expected long, got boolean"
+        );
+
+        assert_eq!(
+            coerce_value(&Schema::Float, Value::Long(1 << 24)).unwrap(),
+            Value::Float(16_777_216.0)
+        );
+        assert_eq!(
+            coerce_value(&Schema::Float, Value::Int((1 << 24) + 1)).unwrap_err().to_string(),
+            "16777217 cannot be represented exactly as float"
+        );
+        assert_eq!(
+            coerce_value(&Schema::Double, Value::Long(-(1 << 53))).unwrap(),
+            Value::Double(-9_007_199_254_740_992.0)
+        );
+        assert_eq!(
+            coerce_value(&Schema::Double, Value::Long((1 << 53) + 1)).unwrap_err().to_string(),
+            "9007199254740993 cannot be represented exactly as double"
+        );
+        assert!(coerce_value(&Schema::Double, Value::Long(i64::MAX)).is_err());
+        assert!(coerce_value(&Schema::Float, Value::Long(i64::MIN)).is_ok());
+
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "counts", "type": ["null", {"type": "array", "items": "int"}]}
+        ]}
+        "#).unwrap();
+        let value = Value::Record(vec![(
+            "counts".to_owned(),
+            Value::Array(vec![Value::Long(1), Value::Long(1 << 40)]),
+        )]);
+        assert_eq!(
+            coerce_value(&schema, value).unwrap_err().to_string(),
+            "field `counts`: 1099511627776 is out of range for int"
+        );
+    }
+
+    #[test]
+    fn record_from_defaults() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "count", "type": "int", "default": 1},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}, "default": "hearts"},
+            {"name": "tags", "type": {"type": "map", "values": "long"}, "default": {"a": 1}}
+        ]}
+        "#).unwrap();
+
+        let mut tags = HashMap::new();
+        tags.insert("a".to_owned(), Value::Long(1));
+        let value = avro_record_from_defaults(&schema).unwrap();
+        assert_eq!(
+            value,
+            Value::Record(vec![
+                ("count".to_owned(), Value::Int(1)),
+                ("comment".to_owned(), Value::Union(None)),
+                ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+                ("tags".to_owned(), Value::Map(tags)),
+            ])
+        );
+        assert!(value.validate(&schema));
+
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "a", "type": "int"},
+            {"name": "b", "type": "string", "default": "b"},
+            {"name": "c", "type": "string"}
+        ]}
+        "#).unwrap();
+        let err = avro_record_from_defaults(&schema).unwrap_err();
+        assert_eq!(err.to_string(), "no default for required fields: a, c");
+    }
+
+    #[test]
+    fn pickle_union() {
+        let schema = Schema::parse_str(r#"
+        ["null", {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "comment", "type": ["null", "string"], "default": null}
+        ]}]
+        "#).unwrap();
+        let dict = |keys: &[&str]| {
+            PickleValue::Dict(
+                keys.iter()
+                    .map(|key| (HashableValue::String(key.to_string()), PickleValue::I64(1)))
+                    .collect(),
+            )
+        };
+        let options = ConversionOptions::default();
+
+        assert_eq!(
+            avro_value_from_pickle_with(&schema, PickleValue::None, &options).unwrap(),
+            Value::Union(None)
+        );
+        assert_eq!(
+            avro_value_from_pickle_with(&schema, dict(&["id"]), &options).unwrap(),
+            Value::Union(Some(Box::new(Value::Record(vec![
+                ("id".to_owned(), Value::Long(1)),
+                ("comment".to_owned(), Value::Union(None)),
+            ]))))
+        );
+
+        // extra keys are ignored as for records which are not in a union, unless rejected
+        assert_eq!(
+            avro_value_from_pickle_with(&schema, dict(&["id", "extra"]), &options).unwrap(),
+            avro_value_from_pickle_with(&schema, dict(&["id"]), &options).unwrap()
+        );
+        let strict_keys = ConversionOptions {
+            reject_extra_keys: true,
+            ..Default::default()
+        };
+        let err = avro_value_from_pickle_with(&schema, dict(&["id", "extra"]), &strict_keys);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "expected Test, got Python dict: not fields of the record: `extra`"
+        );
+
+        let err = avro_value_from_pickle_with(&schema, dict(&["comment"]), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected Test, got Python dict: missing field `id`"
+        );
+        let err = avro_value_from_pickle_with(&schema, PickleValue::I64(1), &options).unwrap_err();
+        assert_eq!(err.to_string(), "expected Test, got Python int");
+
+        // avro-rs 0.4 rejects unions with several non-null branches
+        let err = Schema::parse_str(r#"["null", "long", "string"]"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to parse schema: Unions only support null and type"
+        );
+    }
+
+    #[test]
+    fn pickle_numbers() {
+        use num_bigint::BigInt;
+        use num_traits::One;
+
+        let big = |shift: usize| PickleValue::Int(BigInt::one() << shift);
+        let options = ConversionOptions::default();
+        let convert = |schema: &Schema, value| avro_value_from_pickle_with(schema, value, &options);
+
+        assert_eq!(convert(&Schema::Long, big(40)).unwrap(), Value::Long(1 << 40));
+        assert!(convert(&Schema::Long, big(64)).is_err());
+        assert!(convert(&Schema::Int, PickleValue::I64(1 << 31)).is_err());
+        assert_eq!(
+            convert(&Schema::Int, PickleValue::I64(-(1 << 31))).unwrap(),
+            Value::Int(i32::MIN)
+        );
+        assert_eq!(
+            convert(&Schema::Double, PickleValue::I64(3)).unwrap(),
+            Value::Double(3.0)
+        );
+        assert_eq!(
+            convert(&Schema::Float, big(70)).unwrap(),
+            Value::Float(2f32.powi(70))
+        );
+        assert!(convert(&Schema::Float, PickleValue::F64(1e300)).is_err());
+        assert_eq!(
+            convert(&Schema::Float, PickleValue::F64(f64::INFINITY)).unwrap(),
+            Value::Float(f32::INFINITY)
+        );
+
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [{"name": "count", "type": "int"}]}
+        "#).unwrap();
+        let mut record = BTreeMap::new();
+        record.insert(
+            HashableValue::String("count".to_owned()),
+            PickleValue::I64(1 << 40),
+        );
+        let err = convert(&schema, PickleValue::Dict(record)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected int at `count`, got Python int: 1099511627776 is out of range for an int"
+        );
+    }
+
+    #[test]
+    fn pickle_error_paths() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Order", "fields": [
+            {"name": "items", "type": {"type": "array", "items": {
+                "type": "record", "name": "Item", "fields": [{"name": "sku", "type": "long"}]
+            }}}
+        ]}
+        "#).unwrap();
+        let item = |sku| {
+            let mut item = BTreeMap::new();
+            item.insert(HashableValue::String("sku".to_owned()), sku);
+            PickleValue::Dict(item)
+        };
+        let mut order = BTreeMap::new();
+        order.insert(
+            HashableValue::String("items".to_owned()),
+            PickleValue::List(vec![
+                item(PickleValue::I64(1)),
+                item(PickleValue::String("A-12".to_owned())),
+            ]),
+        );
+
+        let options = ConversionOptions::default();
+        let err = avro_value_from_pickle_with(&schema, PickleValue::Dict(order), &options);
+        let err = err.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected long at `items[1].sku`, got Python str"
+        );
+        let err = err.downcast::<PickleConversionError>().unwrap();
+        assert_eq!(err.path.to_string(), "items[1].sku");
+
+        let err = avro_value_from_pickle_with(&Schema::Int, PickleValue::None, &options);
+        let err = err.unwrap_err();
+        assert_eq!(err.to_string(), "expected int, got Python NoneType");
+    }
+
+    #[test]
+    fn pickle_options() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "name", "type": "string"},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "counts", "type": {"type": "map", "values": "double"}},
+            {"name": "comment", "type": ["null", "string"]}
+        ]}
+        "#).unwrap();
+        let record = |name: PickleValue, suit: PickleValue, count: PickleValue| {
+            let mut counts = BTreeMap::new();
+            counts.insert(HashableValue::I64(1), count);
+            let mut record = BTreeMap::new();
+            record.insert(HashableValue::String("name".to_owned()), name);
+            record.insert(HashableValue::String("suit".to_owned()), suit);
+            record.insert(HashableValue::String("counts".to_owned()), PickleValue::Dict(counts));
+            PickleValue::Dict(record)
+        };
+        let lenient = ConversionOptions {
+            stringify_map_keys: true,
+            missing_nullable_as_null: true,
+            ..Default::default()
+        };
+
+        let value = avro_value_from_pickle_with(
+            &schema,
+            record(
+                PickleValue::Bytes(b"foo".to_vec()),
+                PickleValue::I64(1),
+                PickleValue::I64(2),
+            ),
+            &lenient,
+        )
+        .unwrap();
+        let mut counts = HashMap::new();
+        counts.insert("1".to_owned(), Value::Double(2.0));
+        assert_eq!(
+            value,
+            Value::Record(vec![
+                ("name".to_owned(), Value::String("foo".to_owned())),
+                ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+                ("counts".to_owned(), Value::Map(counts)),
+                ("comment".to_owned(), Value::Union(None)),
+            ])
+        );
+        let pickle = |value: &Value, options| {
+            let pickle = pickle_from_avro(value, options).unwrap();
+            serde_pickle::from_slice::<PickleValue>(&pickle).unwrap()
+        };
+        let suit = |value| match value {
+            PickleValue::Dict(mut fields) => fields
+                .remove(&HashableValue::String("suit".to_owned()))
+                .unwrap(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            suit(pickle(&value, &lenient)),
+            PickleValue::String("hearts".to_owned())
+        );
+        let index = ConversionOptions {
+            enum_mode: AvroEnumMode::Index,
+            ..Default::default()
+        };
+        assert_eq!(suit(pickle(&value, &index)), PickleValue::I64(1));
+
+        // the default options neither stringify map keys nor allow missing nullable fields
+        let value = || {
+            record(
+                PickleValue::String("foo".to_owned()),
+                PickleValue::String("spades".to_owned()),
+                PickleValue::F64(2.0),
+            )
+        };
+        let options = ConversionOptions::default();
+        let err = avro_value_from_pickle_with(&schema, value(), &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected map at `counts`, got Python dict: map key of Python type int is not a str"
+        );
+
+        let strict = ConversionOptions {
+            strict: true,
+            str_bytes_interchange: false,
+            reject_extra_keys: true,
+            ..lenient.clone()
+        };
+        assert!(avro_value_from_pickle_with(&schema, value(), &strict).is_ok());
+        let err = avro_value_from_pickle_with(
+            &schema,
+            record(
+                PickleValue::Bytes(b"foo".to_vec()),
+                PickleValue::String("spades".to_owned()),
+                PickleValue::F64(2.0),
+            ),
+            &strict,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "expected string at `name`, got Python bytes");
+        let err = avro_value_from_pickle_with(
+            &schema,
+            record(
+                PickleValue::String("foo".to_owned()),
+                PickleValue::I64(0),
+                PickleValue::I64(2),
+            ),
+            &strict,
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "expected Suit at `suit`, got Python int");
+
+        let mut extra = match value() {
+            PickleValue::Dict(fields) => fields,
+            _ => unreachable!(),
+        };
+        extra.insert(HashableValue::String("extra".to_owned()), PickleValue::None);
+        let value = avro_value_from_pickle_with(&schema, PickleValue::Dict(extra.clone()), &lenient);
+        assert!(value.is_ok());
+        let err =
+            avro_value_from_pickle_with(&schema, PickleValue::Dict(extra), &strict).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected Test, got Python dict: not fields of the record: `extra`"
+        );
+    }
+
+    #[test]
+    fn pickle_record_modes() {
+        let record = Value::Record(vec![
+            ("zeta".to_owned(), Value::Long(1)),
+            ("alpha".to_owned(), Value::Bytes(b"foo".to_vec())),
+        ]);
+        let position = |pickle: &[u8], name: &[u8]| {
+            pickle.windows(name.len()).position(|window| window == name).unwrap()
+        };
+
+        let pickle = pickle_from_avro(&record, &ConversionOptions::default()).unwrap();
+        assert_eq!(&pickle[..2], &[0x80, 2]);
+        assert!(position(&pickle, b"zeta") < position(&pickle, b"alpha"));
+
+        let options = ConversionOptions {
+            record_mode: AvroRecordMode::Tuple,
+            pickle_protocol: 3,
+            ..Default::default()
+        };
+        let pickle = pickle_from_avro(&record, &options).unwrap();
+        assert_eq!(&pickle[..2], &[0x80, 3]);
+        assert_eq!(
+            serde_pickle::value_from_slice(&pickle).unwrap(),
+            PickleValue::Tuple(vec![PickleValue::I64(1), PickleValue::Bytes(b"foo".to_vec())])
+        );
+
+        let options = ConversionOptions {
+            pickle_protocol: 4,
+            ..Default::default()
+        };
+        assert!(pickle_from_avro(&record, &options).is_err());
+    }
+
+    #[test]
+    fn python_float_keys() {
+        let cases = [
+            (2.0, "2.0"),
+            (-0.0, "-0.0"),
+            (0.1, "0.1"),
+            (1e-4, "0.0001"),
+            (1.5e-5, "1.5e-05"),
+            (1e15, "1000000000000000.0"),
+            (1e16, "1e+16"),
+            (-1.25e300, "-1.25e+300"),
+            (f64::INFINITY, "inf"),
+            (f64::NAN, "nan"),
+        ];
+        let options = ConversionOptions {
+            stringify_map_keys: true,
+            ..Default::default()
+        };
+        for &(x, key) in &cases {
+            assert_eq!(map_key(HashableValue::F64(x), &options).unwrap(), key);
+        }
+    }
 }
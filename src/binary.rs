@@ -0,0 +1,180 @@
+//! Helpers to walk avro binary encoded data in place, without decoding it into values.
+use avro_rs::schema::Schema;
+use failure::{err_msg, Error};
+
+/// Read a zig-zag encoded long, advancing the buffer.
+pub fn read_long(buf: &mut &[u8]) -> Result<i64, Error> {
+    let mut z = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf
+            .first()
+            .ok_or_else(|| err_msg("unexpected end of data"))?;
+        *buf = &buf[1..];
+        if shift >= 64 {
+            return Err(err_msg("variable-length integer overflow"))
+        }
+        z |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            break
+        }
+        shift += 7;
+    }
+    Ok(((z >> 1) as i64) ^ -((z & 1) as i64))
+}
+
+/// Read a zig-zag encoded int, advancing the buffer.
+pub fn read_int(buf: &mut &[u8]) -> Result<i32, Error> {
+    let n = read_long(buf)?;
+    if n < i64::from(i32::MIN) || n > i64::from(i32::MAX) {
+        Err(err_msg("int out of range"))
+    } else {
+        Ok(n as i32)
+    }
+}
+
+/// Read `len` raw bytes, advancing the buffer.
+pub fn read_fixed<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if buf.len() < len {
+        return Err(err_msg("unexpected end of data"))
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+/// Read length-prefixed bytes (or string), advancing the buffer.
+pub fn read_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = read_long(buf)?;
+    if len < 0 {
+        return Err(err_msg("negative bytes length"))
+    }
+    read_fixed(buf, len as usize)
+}
+
+pub fn read_boolean(buf: &mut &[u8]) -> Result<bool, Error> {
+    match read_fixed(buf, 1)?[0] {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(err_msg("not a bool")),
+    }
+}
+
+pub fn read_float(buf: &mut &[u8]) -> Result<f32, Error> {
+    let bytes = read_fixed(buf, 4)?;
+    let mut raw = [0u8; 4];
+    raw.copy_from_slice(bytes);
+    Ok(f32::from_bits(u32::from_le_bytes(raw)))
+}
+
+pub fn read_double(buf: &mut &[u8]) -> Result<f64, Error> {
+    let bytes = read_fixed(buf, 8)?;
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(bytes);
+    Ok(f64::from_bits(u64::from_le_bytes(raw)))
+}
+
+/// Read the header of an array or map block, returning the number of items in the block.
+///
+/// A block with a negative count is followed by its size in bytes, which is skipped.
+pub fn read_block_len(buf: &mut &[u8]) -> Result<usize, Error> {
+    let len = read_long(buf)?;
+    let len = if len < 0 {
+        read_long(buf)?;
+        len.wrapping_neg() as usize
+    } else {
+        len as usize
+    };
+    check_block_len(len, buf)?;
+    Ok(len)
+}
+
+/// Check the number of items of a block against the data left, so that walking a block of
+/// malformed data is bounded by its size.
+///
+/// Items take at least a byte each, except values with no data (e.g. nulls or records with no
+/// fields), whose blocks are limited to one item per byte left.
+fn check_block_len(len: usize, buf: &[u8]) -> Result<(), Error> {
+    if len > buf.len() {
+        return Err(err_msg(format!(
+            "block of {} items is longer than the {} bytes left",
+            len,
+            buf.len()
+        )))
+    }
+    Ok(())
+}
+
+/// Read the branch index of a union. Only `["null", "< type >"]` unions are supported.
+pub fn read_union_branch(buf: &mut &[u8]) -> Result<usize, Error> {
+    match read_long(buf)? {
+        0 => Ok(0),
+        1 => Ok(1),
+        _ => Err(err_msg("union index out of bounds")),
+    }
+}
+
+/// Skip over a value of a given schema, advancing the buffer.
+pub fn skip(schema: &Schema, buf: &mut &[u8]) -> Result<(), Error> {
+    match *schema {
+        Schema::Null => (),
+        Schema::Boolean => {
+            read_fixed(buf, 1)?;
+        },
+        Schema::Int | Schema::Long | Schema::Enum { .. } => {
+            read_long(buf)?;
+        },
+        Schema::Float => {
+            read_fixed(buf, 4)?;
+        },
+        Schema::Double => {
+            read_fixed(buf, 8)?;
+        },
+        Schema::Bytes | Schema::String => {
+            read_bytes(buf)?;
+        },
+        Schema::Fixed { size, .. } => {
+            read_fixed(buf, size)?;
+        },
+        Schema::Array(ref inner) => loop {
+            let len = read_long(buf)?;
+            if len == 0 {
+                break
+            } else if len < 0 {
+                let size = read_long(buf)?;
+                read_fixed(buf, size as usize)?;
+            } else {
+                check_block_len(len as usize, buf)?;
+                for _ in 0..len {
+                    skip(inner, buf)?;
+                }
+            }
+        },
+        Schema::Map(ref inner) => loop {
+            let len = read_long(buf)?;
+            if len == 0 {
+                break
+            } else if len < 0 {
+                let size = read_long(buf)?;
+                read_fixed(buf, size as usize)?;
+            } else {
+                check_block_len(len as usize, buf)?;
+                for _ in 0..len {
+                    read_bytes(buf)?;
+                    skip(inner, buf)?;
+                }
+            }
+        },
+        Schema::Union(ref inner) => {
+            if read_union_branch(buf)? == 1 {
+                skip(inner, buf)?;
+            }
+        },
+        Schema::Record { ref fields, .. } => {
+            for field in fields {
+                skip(&field.schema, buf)?;
+            }
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,320 @@
+//! Avro sort order, as defined in the
+//! [Avro specification](https://avro.apache.org/docs/current/spec.html#order).
+use std::cmp::Ordering;
+use std::os::raw::c_int;
+
+use avro_rs::schema::{RecordFieldOrder, Schema};
+use avro_rs::types::Value;
+use binary::{
+    read_block_len, read_boolean, read_bytes, read_double, read_fixed, read_float, read_long,
+    read_union_branch, skip,
+};
+use core::AvroByteArray;
+use failure::{err_msg, Error};
+use schema::AvroSchema;
+use types::AvroValue;
+
+fn compare_floats(a: f64, b: f64) -> Ordering {
+    // NaN is sorted after every other value
+    a.partial_cmp(&b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+fn as_long(value: &Value) -> Result<i64, Error> {
+    match *value {
+        Value::Int(n) => Ok(i64::from(n)),
+        Value::Long(n) => Ok(n),
+        _ => Err(err_msg("value does not match schema")),
+    }
+}
+
+fn as_double(value: &Value) -> Result<f64, Error> {
+    match *value {
+        Value::Int(n) => Ok(f64::from(n)),
+        Value::Long(n) => Ok(n as f64),
+        Value::Float(x) => Ok(f64::from(x)),
+        Value::Double(x) => Ok(x),
+        _ => Err(err_msg("value does not match schema")),
+    }
+}
+
+fn enum_index(symbols: &[String], value: &Value) -> Result<usize, Error> {
+    match *value {
+        Value::Enum(index, _) => Ok(index as usize),
+        Value::String(ref s) => symbols
+            .iter()
+            .position(|symbol| symbol == s)
+            .ok_or_else(|| err_msg(format!("symbol `{}` is not in enum", s))),
+        _ => Err(err_msg("value does not match schema")),
+    }
+}
+
+/// Compare two values of a schema.
+pub fn compare_values(schema: &Schema, a: &Value, b: &Value) -> Result<Ordering, Error> {
+    match (schema, a, b) {
+        (Schema::Null, _, _) => Ok(Ordering::Equal),
+        (Schema::Boolean, Value::Boolean(a), Value::Boolean(b)) => Ok(a.cmp(b)),
+        (Schema::Int, a, b) | (Schema::Long, a, b) => Ok(as_long(a)?.cmp(&as_long(b)?)),
+        (Schema::Float, a, b) | (Schema::Double, a, b) => {
+            Ok(compare_floats(as_double(a)?, as_double(b)?))
+        },
+        (Schema::Bytes, Value::Bytes(a), Value::Bytes(b))
+        | (Schema::Fixed { .. }, Value::Fixed(_, a), Value::Fixed(_, b)) => Ok(a.cmp(b)),
+        (Schema::String, Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+        (Schema::Enum { symbols, .. }, a, b) => {
+            Ok(enum_index(symbols, a)?.cmp(&enum_index(symbols, b)?))
+        },
+        (Schema::Union(inner), Value::Union(a), Value::Union(b)) => match (a, b) {
+            (None, None) => Ok(Ordering::Equal),
+            (None, Some(_)) => Ok(Ordering::Less),
+            (Some(_), None) => Ok(Ordering::Greater),
+            (Some(a), Some(b)) => compare_values(inner, a, b),
+        },
+        (Schema::Array(inner), Value::Array(a), Value::Array(b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                match compare_values(inner, a, b)? {
+                    Ordering::Equal => (),
+                    ordering => return Ok(ordering),
+                }
+            }
+            Ok(a.len().cmp(&b.len()))
+        },
+        (Schema::Map(_), _, _) => Err(err_msg("map values cannot be compared")),
+        (Schema::Record { fields, .. }, Value::Record(a), Value::Record(b)) => {
+            if a.len() != fields.len() || b.len() != fields.len() {
+                return Err(err_msg("value does not match schema"))
+            }
+            for (field, (a, b)) in fields.iter().zip(a.iter().zip(b.iter())) {
+                let ordering = match field.order {
+                    RecordFieldOrder::Ignore => continue,
+                    RecordFieldOrder::Ascending => compare_values(&field.schema, &a.1, &b.1)?,
+                    RecordFieldOrder::Descending => {
+                        compare_values(&field.schema, &a.1, &b.1)?.reverse()
+                    },
+                };
+                if ordering != Ordering::Equal {
+                    return Ok(ordering)
+                }
+            }
+            Ok(Ordering::Equal)
+        },
+        _ => Err(err_msg("value does not match schema")),
+    }
+}
+
+/// Compare two binary encoded values of a schema, without decoding them.
+///
+/// Only the data needed to decide the ordering is read.
+pub fn compare_encoded(schema: &Schema, a: &mut &[u8], b: &mut &[u8]) -> Result<Ordering, Error> {
+    match *schema {
+        Schema::Null => Ok(Ordering::Equal),
+        Schema::Boolean => Ok(read_boolean(a)?.cmp(&read_boolean(b)?)),
+        Schema::Int | Schema::Long | Schema::Enum { .. } => Ok(read_long(a)?.cmp(&read_long(b)?)),
+        Schema::Float => Ok(compare_floats(
+            f64::from(read_float(a)?),
+            f64::from(read_float(b)?),
+        )),
+        Schema::Double => Ok(compare_floats(read_double(a)?, read_double(b)?)),
+        Schema::Bytes | Schema::String => Ok(read_bytes(a)?.cmp(read_bytes(b)?)),
+        Schema::Fixed { size, .. } => Ok(read_fixed(a, size)?.cmp(read_fixed(b, size)?)),
+        Schema::Union(ref inner) => match (read_union_branch(a)?, read_union_branch(b)?) {
+            (1, 1) => compare_encoded(inner, a, b),
+            (branch_a, branch_b) => Ok(branch_a.cmp(&branch_b)),
+        },
+        Schema::Array(ref inner) => {
+            let mut len_a = read_block_len(a)?;
+            let mut len_b = read_block_len(b)?;
+            loop {
+                match (len_a, len_b) {
+                    (0, 0) => return Ok(Ordering::Equal),
+                    (0, _) => return Ok(Ordering::Less),
+                    (_, 0) => return Ok(Ordering::Greater),
+                    _ => (),
+                }
+                match compare_encoded(inner, a, b)? {
+                    Ordering::Equal => (),
+                    ordering => return Ok(ordering),
+                }
+                len_a -= 1;
+                len_b -= 1;
+                if len_a == 0 {
+                    len_a = read_block_len(a)?;
+                }
+                if len_b == 0 {
+                    len_b = read_block_len(b)?;
+                }
+            }
+        },
+        Schema::Map(_) => Err(err_msg("map values cannot be compared")),
+        Schema::Record { ref fields, .. } => {
+            for field in fields {
+                let ordering = match field.order {
+                    RecordFieldOrder::Ignore => {
+                        skip(&field.schema, a)?;
+                        skip(&field.schema, b)?;
+                        continue
+                    },
+                    RecordFieldOrder::Ascending => compare_encoded(&field.schema, a, b)?,
+                    RecordFieldOrder::Descending => compare_encoded(&field.schema, a, b)?.reverse(),
+                };
+                if ordering != Ordering::Equal {
+                    return Ok(ordering)
+                }
+            }
+            Ok(Ordering::Equal)
+        },
+    }
+}
+
+fn ordering_to_c_int(ordering: Ordering) -> c_int {
+    match ordering {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+ffi_fn! {
+    /// Compare two avro values following the avro sort order of a schema.
+    ///
+    /// Return a negative number, zero or a positive number if the first value is respectively
+    /// lower than, equal to or greater than the second one.
+    unsafe fn avro_value_compare(
+        schema: *const AvroSchema,
+        a: *const AvroValue,
+        b: *const AvroValue
+    ) -> Result<c_int> {
+        let schema = &*(schema as *const Schema);
+        let a = &*(a as *const Value);
+        let b = &*(b as *const Value);
+        Ok(ordering_to_c_int(compare_values(schema, a, b)?))
+    }
+}
+
+ffi_fn! {
+    /// Compare two avro serialized datums following the avro sort order of a schema, without
+    /// decoding them.
+    ///
+    /// Return a negative number, zero or a positive number if the first datum is respectively
+    /// lower than, equal to or greater than the second one.
+    unsafe fn avro_binary_compare(
+        schema: *const AvroSchema,
+        a: *const AvroByteArray,
+        b: *const AvroByteArray
+    ) -> Result<c_int> {
+        let schema = &*(schema as *const Schema);
+        let mut a = (&*a).as_slice();
+        let mut b = (&*b).as_slice();
+        Ok(ordering_to_c_int(compare_encoded(schema, &mut a, &mut b)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::to_avro_datum;
+
+    fn compare_both(schema: &Schema, a: Value, b: Value) -> Ordering {
+        let ordering = compare_values(schema, &a, &b).unwrap();
+        let a = to_avro_datum(schema, a).unwrap();
+        let b = to_avro_datum(schema, b).unwrap();
+        let encoded_ordering = compare_encoded(schema, &mut &a[..], &mut &b[..]).unwrap();
+        assert_eq!(ordering, encoded_ordering);
+        ordering
+    }
+
+    #[test]
+    fn compare_records() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "ignored", "type": "string", "order": "ignore"},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "score", "type": "long", "order": "descending"},
+            {"name": "tags", "type": {"type": "array", "items": "string"}}
+        ]}
+        "#).unwrap();
+        let record = |ignored: &str, suit: i32, score: i64, tags: &[&str]| {
+            Value::Record(vec![
+                ("ignored".to_owned(), Value::String(ignored.to_owned())),
+                (
+                    "suit".to_owned(),
+                    Value::Enum(suit, ["spades", "hearts"][suit as usize].to_owned()),
+                ),
+                ("score".to_owned(), Value::Long(score)),
+                (
+                    "tags".to_owned(),
+                    Value::Array(tags.iter().map(|t| Value::String(t.to_string())).collect()),
+                ),
+            ])
+        };
+
+        assert_eq!(
+            compare_both(
+                &schema,
+                record("a", 0, 1, &["x"]),
+                record("b", 0, 1, &["x"])
+            ),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare_both(&schema, record("a", 1, 1, &[]), record("a", 0, 1, &[])),
+            Ordering::Greater
+        );
+        assert_eq!(
+            compare_both(&schema, record("a", 0, 2, &[]), record("a", 0, 1, &[])),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_both(
+                &schema,
+                record("a", 0, 1, &["x"]),
+                record("a", 0, 1, &["x", "a"])
+            ),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_both(
+                &schema,
+                record("a", 0, 1, &["y"]),
+                record("a", 0, 1, &["x", "a"])
+            ),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn compare_unions() {
+        let schema = Schema::parse_str(r#"["null", "double"]"#).unwrap();
+        let double = |x| Value::Union(Some(Box::new(Value::Double(x))));
+        assert_eq!(
+            compare_both(&schema, Value::Union(None), double(-1.0)),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare_both(&schema, double(2.5), double(-1.0)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn compare_malformed_blocks() {
+        // a block of i64::MAX nulls, which take no bytes
+        let datum = [0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00];
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "null"}"#).unwrap();
+        assert!(compare_encoded(&schema, &mut &datum[..], &mut &datum[..]).is_err());
+
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "nulls", "type": {"type": "array", "items": "null"}, "order": "ignore"}
+        ]}
+        "#).unwrap();
+        assert!(compare_encoded(&schema, &mut &datum[..], &mut &datum[..]).is_err());
+    }
+
+    #[test]
+    fn compare_maps() {
+        let schema = Schema::parse_str(r#"{"type": "map", "values": "long"}"#).unwrap();
+        let map = Value::Map(Default::default());
+        assert!(compare_values(&schema, &map, &map).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Writing of avro object container files, with deterministic output if needed.
+use std::io::Write;
+
+use avro_rs::schema::Schema;
+use avro_rs::types::{ToAvro, Value};
+use avro_rs::{self, Codec};
+use encode::{encode_bytes, encode_long, encode_ref};
+use failure::{err_msg, Error};
+use serde_json;
+
+const SYNC_SIZE: usize = 16;
+const SYNC_INTERVAL: usize = 1000 * SYNC_SIZE;
+
+const AVRO_OBJECT_HEADER: &[u8] = &[b'O', b'b', b'j', 1u8];
+
+/// Derive a sync marker from some bytes, with two rounds of FNV-1a.
+fn marker_from(bytes: &[u8]) -> [u8; SYNC_SIZE] {
+    let mut marker = [0u8; SYNC_SIZE];
+    let bases = [0xcbf2_9ce4_8422_2325u64, 0x6c62_272e_07bb_0142u64];
+    for (chunk, &base) in marker.chunks_mut(8).zip(bases.iter()) {
+        let hash = bytes.iter().fold(base, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        chunk.copy_from_slice(&hash.to_le_bytes());
+    }
+    marker
+}
+
+/// Writer of avro object container files: `avro_rs::Writer`, or a deterministic writer.
+pub enum Writer<'a, W> {
+    Default(avro_rs::Writer<'a, W>),
+    Deterministic(DeterministicWriter<'a, W>),
+}
+
+impl<'a, W: Write> Writer<'a, W> {
+    pub fn with_codec(schema: &'a Schema, writer: W, codec: Codec) -> Writer<'a, W> {
+        Writer::Default(avro_rs::Writer::with_codec(schema, writer, codec))
+    }
+
+    /// Create a writer whose output only depends on the values appended to it.
+    pub fn deterministic(
+        schema: &'a Schema,
+        writer: W,
+        codec: Codec,
+    ) -> Result<Writer<'a, W>, Error> {
+        DeterministicWriter::new(schema, writer, codec).map(Writer::Deterministic)
+    }
+
+    pub fn schema(&self) -> &'a Schema {
+        match *self {
+            Writer::Default(ref writer) => writer.schema(),
+            Writer::Deterministic(ref writer) => writer.schema,
+        }
+    }
+
+    pub fn append_value_ref(&mut self, value: &Value) -> Result<usize, Error> {
+        match *self {
+            Writer::Default(ref mut writer) => writer.append_value_ref(value),
+            Writer::Deterministic(ref mut writer) => writer.append_value_ref(value),
+        }
+    }
+
+    pub fn flush(&mut self) -> Result<usize, Error> {
+        match *self {
+            Writer::Default(ref mut writer) => writer.flush(),
+            Writer::Deterministic(ref mut writer) => writer.flush(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        match self {
+            Writer::Default(writer) => writer.into_inner(),
+            Writer::Deterministic(writer) => writer.into_inner(),
+        }
+    }
+}
+
+/// Writer of avro object container files whose output only depends on the values appended to
+/// it: map entries sorted by key, header metadata in a fixed order and a sync marker derived
+/// from the schema.
+pub struct DeterministicWriter<'a, W> {
+    schema: &'a Schema,
+    writer: W,
+    buffer: Vec<u8>,
+    num_values: usize,
+    codec: Codec,
+    marker: [u8; SYNC_SIZE],
+    has_header: bool,
+}
+
+impl<'a, W: Write> DeterministicWriter<'a, W> {
+    pub fn new(
+        schema: &'a Schema,
+        writer: W,
+        codec: Codec,
+    ) -> Result<DeterministicWriter<'a, W>, Error> {
+        Ok(DeterministicWriter {
+            schema,
+            writer,
+            buffer: Vec::with_capacity(SYNC_INTERVAL),
+            num_values: 0,
+            codec,
+            marker: marker_from(serde_json::to_string(schema)?.as_bytes()),
+            has_header: false,
+        })
+    }
+
+    /// Append a value, after validating it against the schema.
+    ///
+    /// Return the number of bytes written, which might be 0 as values are buffered until
+    /// `flush` is called or enough of them are appended.
+    pub fn append_value_ref(&mut self, value: &Value) -> Result<usize, Error> {
+        if !value.validate(self.schema) {
+            return Err(err_msg("value does not match schema"))
+        }
+
+        let n = if !self.has_header {
+            let header = self.header()?;
+            self.writer.write_all(&header)?;
+            self.has_header = true;
+            header.len()
+        } else {
+            0
+        };
+
+        encode_ref(value, self.schema, true, &mut self.buffer);
+        self.num_values += 1;
+
+        if self.buffer.len() >= SYNC_INTERVAL {
+            return self.flush().map(|b| b + n)
+        }
+
+        Ok(n)
+    }
+
+    /// Write the buffered values as a block, returning the number of bytes written.
+    pub fn flush(&mut self) -> Result<usize, Error> {
+        if self.num_values == 0 {
+            return Ok(0)
+        }
+
+        self.codec.compress(&mut self.buffer)?;
+
+        let mut block = Vec::new();
+        encode_long(self.num_values as i64, &mut block);
+        encode_long(self.buffer.len() as i64, &mut block);
+        block.extend_from_slice(&self.buffer);
+        block.extend_from_slice(&self.marker);
+        self.writer.write_all(&block)?;
+
+        self.buffer.clear();
+        self.num_values = 0;
+
+        Ok(block.len())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Header of the file: magic bytes, metadata and sync marker.
+    fn header(&self) -> Result<Vec<u8>, Error> {
+        let schema = serde_json::to_string(self.schema)?.into_bytes();
+        let codec = match self.codec.avro() {
+            Value::Bytes(codec) => codec,
+            _ => unreachable!(),
+        };
+
+        let mut header = Vec::new();
+        header.extend_from_slice(AVRO_OBJECT_HEADER);
+        // the metadata map is always written in the same order
+        encode_long(2, &mut header);
+        encode_bytes(b"avro.schema", &mut header);
+        encode_bytes(&schema, &mut header);
+        encode_bytes(b"avro.codec", &mut header);
+        encode_bytes(&codec, &mut header);
+        header.push(0);
+        header.extend_from_slice(&self.marker);
+
+        Ok(header)
+    }
+}
@@ -0,0 +1,314 @@
+//! Differences between avro values, and patching of records with partial records.
+use std::ptr;
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use avro_utils::conform_value;
+use core::AvroStr;
+use failure::{err_msg, Error};
+use path::{Path, PathSegment};
+use schema::AvroSchema;
+use types::AvroValue;
+
+pub struct AvroDiff;
+
+/// A value which differs between two values of a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub path: Path,
+    /// `None` for a map entry added by the new value.
+    pub old: Option<Value>,
+    /// `None` for a map entry removed by the new value.
+    pub new: Option<Value>,
+}
+
+fn field<'a>(fields: &'a [(String, Value)], name: &str) -> Result<&'a Value, Error> {
+    fields
+        .iter()
+        .find(|&(field, _)| field == name)
+        .map(|(_, value)| value)
+        .ok_or_else(|| err_msg(format!("field `{}` is not in record", name)))
+}
+
+/// Collect the changes between two values of a schema.
+///
+/// Records, maps and non-null unions are compared item by item, any other value is compared as
+/// a whole.
+pub fn diff_values(
+    schema: &Schema,
+    path: &Path,
+    old: &Value,
+    new: &Value,
+    changes: &mut Vec<Change>,
+) -> Result<(), Error> {
+    match (schema, old, new) {
+        (Schema::Record { fields, .. }, Value::Record(old_fields), Value::Record(new_fields)) => {
+            for schema_field in fields {
+                let name = &schema_field.name;
+                diff_values(
+                    &schema_field.schema,
+                    &path.child(PathSegment::Field(name.clone())),
+                    field(old_fields, name)?,
+                    field(new_fields, name)?,
+                    changes,
+                )?;
+            }
+        },
+        (Schema::Map(inner), Value::Map(old_items), Value::Map(new_items)) => {
+            let mut keys = old_items.keys().chain(new_items.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = path.child(PathSegment::Key(key.clone()));
+                match (old_items.get(key), new_items.get(key)) {
+                    (Some(old), Some(new)) => diff_values(inner, &path, old, new, changes)?,
+                    (old, new) => changes.push(Change {
+                        path,
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    }),
+                }
+            }
+        },
+        (Schema::Union(inner), Value::Union(Some(old)), Value::Union(Some(new))) => {
+            diff_values(inner, path, old, new, changes)?
+        },
+        _ => {
+            if !old.validate(schema) || !new.validate(schema) {
+                return Err(err_msg(format!(
+                    "value at `{}` does not match schema",
+                    path
+                )))
+            }
+            if old != new {
+                changes.push(Change {
+                    path: path.clone(),
+                    old: Some(old.clone()),
+                    new: Some(new.clone()),
+                });
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Apply a partial record to a value of a schema.
+///
+/// The patch is a record or a map holding some of the fields of the record. Nested records
+/// (also within non-null unions) are patched the same way, other fields are replaced.
+pub fn patch_value(schema: &Schema, base: &Value, patch: &Value) -> Result<Value, Error> {
+    let patch_fields = match *patch {
+        Value::Record(ref fields) => fields.iter().map(|(name, value)| (name, value)).collect(),
+        Value::Map(ref items) => items.iter().collect::<Vec<_>>(),
+        _ => return conform_value(schema, patch.clone()),
+    };
+
+    match (schema, base) {
+        (Schema::Record { fields, lookup, .. }, Value::Record(base_fields)) => {
+            let mut patched = base_fields.clone();
+            for (name, value) in patch_fields {
+                let position = *lookup
+                    .get(name)
+                    .ok_or_else(|| err_msg(format!("field `{}` is not in record schema", name)))?;
+                let base_value = field(base_fields, name)?;
+                let value = patch_value(&fields[position].schema, base_value, value)
+                    .map_err(|err| err_msg(format!("field `{}`: {}", name, err)))?;
+                match patched.iter_mut().find(|field| field.0 == *name) {
+                    Some(field) => field.1 = value,
+                    None => unreachable!(),
+                }
+            }
+            Ok(Value::Record(patched))
+        },
+        (Schema::Union(inner), Value::Union(Some(base))) => Ok(Value::Union(Some(Box::new(
+            patch_value(inner, base, patch)?,
+        )))),
+        _ => conform_value(schema, patch.clone()),
+    }
+}
+
+ffi_fn! {
+    /// Compute the differences between two values of a schema. Records, maps and non-null
+    /// unions are compared item by item, any other value is compared as a whole.
+    ///
+    /// The values are not consumed. The diff must be freed with `avro_diff_free`.
+    unsafe fn avro_value_diff(
+        schema: *const AvroSchema,
+        old: *const AvroValue,
+        new: *const AvroValue
+    ) -> Result<*mut AvroDiff> {
+        let schema = &*(schema as *const Schema);
+        let old = &*(old as *const Value);
+        let new = &*(new as *const Value);
+        let mut changes = Vec::new();
+        diff_values(schema, &Path::default(), old, new, &mut changes)?;
+        Ok(Box::into_raw(Box::new(changes)) as *mut AvroDiff)
+    }
+}
+
+unsafe fn change<'a>(diff: *const AvroDiff, index: usize) -> Result<&'a Change, Error> {
+    let changes = &*(diff as *const Vec<Change>);
+    changes.get(index).ok_or_else(|| {
+        err_msg(format!(
+            "change index {} out of bounds for a diff of {} changes",
+            index,
+            changes.len()
+        ))
+    })
+}
+
+fn value_ptr(value: Option<&Value>) -> *const AvroValue {
+    value.map_or(ptr::null(), |value| value as *const Value as *const AvroValue)
+}
+
+ffi_fn! {
+    /// Number of changes in a diff.
+    unsafe fn avro_diff_len(diff: *const AvroDiff) -> Result<usize> {
+        Ok((&*(diff as *const Vec<Change>)).len())
+    }
+}
+
+ffi_fn! {
+    /// Path of a change, such as `a.b` or `tags["env"]`, to be used with `avro_value_get_path`.
+    /// The string must be freed with `avro_str_free`.
+    unsafe fn avro_diff_path(diff: *const AvroDiff, index: usize) -> Result<AvroStr> {
+        Ok(AvroStr::from_string(change(diff, index)?.path.to_string()))
+    }
+}
+
+ffi_fn! {
+    /// Old value of a change, borrowed from the diff. Null for a map entry added by the new
+    /// value.
+    unsafe fn avro_diff_old_value(diff: *const AvroDiff, index: usize) -> Result<*const AvroValue> {
+        Ok(value_ptr(change(diff, index)?.old.as_ref()))
+    }
+}
+
+ffi_fn! {
+    /// New value of a change, borrowed from the diff. Null for a map entry removed by the new
+    /// value.
+    unsafe fn avro_diff_new_value(diff: *const AvroDiff, index: usize) -> Result<*const AvroValue> {
+        Ok(value_ptr(change(diff, index)?.new.as_ref()))
+    }
+}
+
+ffi_fn! {
+    /// Free a diff.
+    unsafe fn avro_diff_free(diff: *mut AvroDiff) {
+        if !diff.is_null() {
+            drop(Box::from_raw(diff as *mut Vec<Change>));
+        }
+    }
+}
+
+ffi_fn! {
+    /// Apply a partial record, given as a record or a map holding some of its fields, to a
+    /// record of a schema. Nested records are patched the same way, other fields are replaced.
+    ///
+    /// The values are not consumed. The patched record is a new value.
+    unsafe fn avro_value_patch(
+        schema: *const AvroSchema,
+        base: *const AvroValue,
+        patch: *const AvroValue
+    ) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        let base = &*(base as *const Value);
+        let patch = &*(patch as *const Value);
+        Ok(ffi_avro_value!(patch_value(schema, base, patch)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn schema() -> Schema {
+        Schema::parse_str(
+            r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "address", "type": ["null", {"type": "record", "name": "Address", "fields": [
+                {"name": "city", "type": "string"},
+                {"name": "zip", "type": "string"}
+            ]}]},
+            {"name": "tags", "type": {"type": "map", "values": "string"}}
+        ]}
+        "#,
+        )
+        .unwrap()
+    }
+
+    fn record(id: i64, city: &str, tags: &[(&str, &str)]) -> Value {
+        Value::Record(vec![
+            ("id".to_owned(), Value::Long(id)),
+            (
+                "address".to_owned(),
+                Value::Union(Some(Box::new(Value::Record(vec![
+                    ("city".to_owned(), Value::String(city.to_owned())),
+                    ("zip".to_owned(), Value::String("75001".to_owned())),
+                ])))),
+            ),
+            (
+                "tags".to_owned(),
+                Value::Map(
+                    tags.iter()
+                        .map(|&(k, v)| (k.to_owned(), Value::String(v.to_owned())))
+                        .collect::<HashMap<_, _>>(),
+                ),
+            ),
+        ])
+    }
+
+    #[test]
+    fn diff_records() {
+        let schema = schema();
+        let old = record(1, "Paris", &[("env", "prod"), ("team", "a")]);
+        let new = record(1, "Lyon", &[("env", "prod"), ("owner", "b")]);
+
+        let mut changes = Vec::new();
+        diff_values(&schema, &Path::default(), &old, &new, &mut changes).unwrap();
+        let changes = changes
+            .into_iter()
+            .map(|change| (change.path.to_string(), change.old, change.new))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (
+                    "address.city".to_owned(),
+                    Some(Value::String("Paris".to_owned())),
+                    Some(Value::String("Lyon".to_owned())),
+                ),
+                (
+                    r#"tags["owner"]"#.to_owned(),
+                    None,
+                    Some(Value::String("b".to_owned())),
+                ),
+                (
+                    r#"tags["team"]"#.to_owned(),
+                    Some(Value::String("a".to_owned())),
+                    None,
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn patch_records() {
+        let schema = schema();
+        let base = record(1, "Paris", &[("env", "prod")]);
+
+        let mut patch = HashMap::new();
+        patch.insert("id".to_owned(), Value::Int(2));
+        patch.insert(
+            "address".to_owned(),
+            Value::Record(vec![("city".to_owned(), Value::String("Lyon".to_owned()))]),
+        );
+        let patched = patch_value(&schema, &base, &Value::Map(patch)).unwrap();
+        assert_eq!(patched, record(2, "Lyon", &[("env", "prod")]));
+
+        let unknown = Value::Record(vec![("name".to_owned(), Value::Null)]);
+        assert!(patch_value(&schema, &base, &unknown).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+//! Avro binary encoding of borrowed values, giving the same bytes as `avro_rs::to_avro_datum`.
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use failure::{err_msg, Error};
+
+/// Variable-length encoding of avro-rs 0.4, see `variable_size`.
+fn encode_variable(mut z: i64, buffer: &mut Vec<u8>) {
+    while z > 0x7F {
+        buffer.push(0x80 | (z & 0x7F) as u8);
+        z >>= 7;
+    }
+    buffer.push((z & 0x7F) as u8);
+}
+
+pub fn encode_long(n: i64, buffer: &mut Vec<u8>) {
+    encode_variable(zigzag_long(n), buffer)
+}
+
+fn encode_int(n: i32, buffer: &mut Vec<u8>) {
+    encode_variable(zigzag_int(n), buffer)
+}
+
+pub fn encode_bytes(bytes: &[u8], buffer: &mut Vec<u8>) {
+    encode_long(bytes.len() as i64, buffer);
+    buffer.extend_from_slice(bytes);
+}
+
+/// Encode a value of a schema in avro binary format.
+///
+/// The value is assumed to be valid for the schema. Arrays and maps are written in a single
+/// block followed by the terminating zero, even when empty. Map entries are sorted by key if
+/// `sort_maps` is set, making the encoding deterministic.
+pub fn encode_ref(value: &Value, schema: &Schema, sort_maps: bool, buffer: &mut Vec<u8>) {
+    match (value, schema) {
+        (Value::Null, _) => (),
+        (Value::Boolean(b), _) => buffer.push(*b as u8),
+        (Value::Int(n), _) => encode_int(*n, buffer),
+        (Value::Long(n), _) => encode_long(*n, buffer),
+        (Value::Float(x), _) => buffer.extend_from_slice(&x.to_bits().to_le_bytes()),
+        (Value::Double(x), _) => buffer.extend_from_slice(&x.to_bits().to_le_bytes()),
+        (Value::Bytes(bytes), _) => encode_bytes(bytes, buffer),
+        (Value::String(s), Schema::Enum { symbols, .. }) => {
+            if let Some(index) = symbols.iter().position(|symbol| symbol == s) {
+                encode_int(index as i32, buffer);
+            }
+        },
+        (Value::String(s), _) => encode_bytes(s.as_bytes(), buffer),
+        (Value::Fixed(_, bytes), _) => buffer.extend_from_slice(bytes),
+        (Value::Enum(index, _), _) => encode_int(*index, buffer),
+        (Value::Union(None), _) => buffer.push(0),
+        (Value::Union(Some(item)), Schema::Union(inner)) => {
+            buffer.push(2);
+            encode_ref(item, inner, sort_maps, buffer);
+        },
+        (Value::Array(items), Schema::Array(inner)) => {
+            encode_long(items.len() as i64, buffer);
+            for item in items {
+                encode_ref(item, inner, sort_maps, buffer);
+            }
+            buffer.push(0);
+        },
+        (Value::Map(items), Schema::Map(inner)) => {
+            encode_long(items.len() as i64, buffer);
+            let mut entries = items.iter().collect::<Vec<_>>();
+            if sort_maps {
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+            }
+            for (key, item) in entries {
+                encode_bytes(key.as_bytes(), buffer);
+                encode_ref(item, inner, sort_maps, buffer);
+            }
+            buffer.push(0);
+        },
+        (
+            Value::Record(fields),
+            Schema::Record {
+                fields: schema_fields,
+                ..
+            },
+        ) => {
+            for (field, schema_field) in fields.iter().zip(schema_fields.iter()) {
+                encode_ref(&field.1, &schema_field.schema, sort_maps, buffer);
+            }
+        },
+        _ => (),
+    }
+}
+
+/// Zigzag encoding of a long as avro-rs 0.4 computes it, shifting the sign by 31 bits rather
+/// than 63: longs beyond the range of ints do not get the encoding of the specification.
+fn zigzag_long(n: i64) -> i64 {
+    (n << 1) ^ (n >> 31)
+}
+
+/// Zigzag encoding of an int as avro-rs 0.4 computes it, before widening it to a long: ints
+/// beyond 2^30 in magnitude do not get the encoding of the specification.
+fn zigzag_int(n: i32) -> i64 {
+    i64::from((n << 1) ^ (n >> 31))
+}
+
+/// Number of bytes of the variable-length encoding of avro-rs 0.4, which writes any negative
+/// zigzag value as a single byte.
+fn variable_size(mut z: i64) -> usize {
+    let mut size = 1;
+    while z > 0x7F {
+        size += 1;
+        z >>= 7;
+    }
+    size
+}
+
+fn long_size(n: i64) -> usize {
+    variable_size(zigzag_long(n))
+}
+
+fn int_size(n: i32) -> usize {
+    variable_size(zigzag_int(n))
+}
+
+fn bytes_size(len: usize) -> usize {
+    long_size(len as i64) + len
+}
+
+/// Number of bytes `avro_rs::to_avro_datum` writes for a value of a schema, without encoding it.
+pub fn encoded_size(value: &Value, schema: &Schema) -> usize {
+    match (value, schema) {
+        (Value::Null, _) => 0,
+        (Value::Boolean(_), _) => 1,
+        (Value::Int(n), _) => int_size(*n),
+        (Value::Long(n), _) => long_size(*n),
+        (Value::Float(_), _) => 4,
+        (Value::Double(_), _) => 8,
+        (Value::Bytes(bytes), _) => bytes_size(bytes.len()),
+        (Value::String(s), Schema::Enum { symbols, .. }) => symbols
+            .iter()
+            .position(|symbol| symbol == s)
+            .map_or(0, |index| int_size(index as i32)),
+        (Value::String(s), _) => bytes_size(s.len()),
+        (Value::Fixed(_, bytes), _) => bytes.len(),
+        (Value::Enum(index, _), _) => int_size(*index),
+        (Value::Union(None), _) => 1,
+        (Value::Union(Some(item)), Schema::Union(inner)) => 1 + encoded_size(item, inner),
+        (Value::Array(items), Schema::Array(inner)) => {
+            let mut size = long_size(items.len() as i64) + 1;
+            for item in items {
+                size += encoded_size(item, inner);
+            }
+            size
+        },
+        (Value::Map(items), Schema::Map(inner)) => {
+            let mut size = long_size(items.len() as i64) + 1;
+            for (key, item) in items {
+                size += bytes_size(key.len()) + encoded_size(item, inner);
+            }
+            size
+        },
+        (
+            Value::Record(fields),
+            Schema::Record {
+                fields: schema_fields,
+                ..
+            },
+        ) => fields
+            .iter()
+            .zip(schema_fields.iter())
+            .map(|(field, schema_field)| encoded_size(&field.1, &schema_field.schema))
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// Write a single avro datum of a borrowed value, after validating it against the schema.
+pub fn to_avro_datum_ref(
+    schema: &Schema,
+    value: &Value,
+    sort_maps: bool,
+) -> Result<Vec<u8>, Error> {
+    if !value.validate(schema) {
+        return Err(err_msg("value does not match schema"))
+    }
+    let mut buffer = Vec::new();
+    encode_ref(value, schema, sort_maps, &mut buffer);
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::to_avro_datum;
+    use random::{AvroRandomOptions, RandomValues};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    #[test]
+    fn encode_like_avro_rs() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "score", "type": "double"},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "tags", "type": {"type": "map", "values": "int"}},
+            {"name": "hash", "type": {"type": "fixed", "name": "md5", "size": 2}}
+        ]}
+        "#).unwrap();
+        let mut tags = HashMap::new();
+        tags.insert("env".to_owned(), Value::Int(-300));
+        let value = Value::Record(vec![
+            ("id".to_owned(), Value::Long(-1_234_567_890)),
+            ("score".to_owned(), Value::Double(-1.5)),
+            (
+                "comment".to_owned(),
+                Value::Union(Some(Box::new(Value::String("hi".to_owned())))),
+            ),
+            ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+            ("tags".to_owned(), Value::Map(tags)),
+            ("hash".to_owned(), Value::Fixed(2, vec![1, 2])),
+        ]);
+
+        assert_eq!(
+            to_avro_datum_ref(&schema, &value, false).unwrap(),
+            to_avro_datum(&schema, value).unwrap()
+        );
+
+        let mut random = RandomValues::new(3, AvroRandomOptions::default()).unwrap();
+        for _ in 0..100 {
+            let value = random.value(&schema).unwrap();
+            assert_eq!(
+                to_avro_datum_ref(&schema, &value, false).unwrap(),
+                to_avro_datum(&schema, value).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn encode_empty_array() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "ids", "type": {"type": "array", "items": "long"}},
+            {"name": "id", "type": "long"}
+        ]}
+        "#).unwrap();
+        let value = Value::Record(vec![
+            ("ids".to_owned(), Value::Array(vec![])),
+            ("id".to_owned(), Value::Long(5)),
+        ]);
+
+        // an empty block before the terminating zero, as avro-rs writes them
+        let datum = to_avro_datum_ref(&schema, &value, false).unwrap();
+        assert_eq!(datum, vec![0, 0, 10]);
+        assert_eq!(datum, to_avro_datum(&schema, value).unwrap());
+    }
+
+    #[test]
+    fn encoded_sizes() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "hash", "type": {"type": "fixed", "name": "md5", "size": 16}},
+            {"name": "tags", "type": {"type": "map", "values": {"type": "array", "items": "float"}}}
+        ]}
+        "#).unwrap();
+
+        let mut random = RandomValues::new(7, AvroRandomOptions::default()).unwrap();
+        for _ in 0..100 {
+            let value = random.value(&schema).unwrap();
+            let size = encoded_size(&value, &schema);
+            assert_eq!(size, to_avro_datum(&schema, value).unwrap().len());
+        }
+
+        let values = vec![
+            (Schema::Long, Value::Long(i64::MAX)),
+            (Schema::Long, Value::Long(-(1 << 40))),
+            (Schema::Int, Value::Int(i32::MIN)),
+            (Schema::Int, Value::Int(1 << 30)),
+            (Schema::Array(Rc::new(Schema::Int)), Value::Array(vec![])),
+            (Schema::Map(Rc::new(Schema::Int)), Value::Map(HashMap::new())),
+        ];
+        for (schema, value) in values {
+            let size = encoded_size(&value, &schema);
+            assert_eq!(size, to_avro_datum(&schema, value).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn encode_sorted_maps() {
+        let schema = Schema::parse_str(r#"{"type": "map", "values": "int"}"#).unwrap();
+        let mut items = HashMap::new();
+        for key in &["c", "a", "d", "b"] {
+            items.insert(key.to_string(), Value::Int(1));
+        }
+        let datum = to_avro_datum_ref(&schema, &Value::Map(items), true).unwrap();
+        assert_eq!(
+            datum,
+            vec![8, 2, b'a', 2, 2, b'b', 2, 2, b'c', 2, 2, b'd', 2, 0]
+        );
+    }
+}
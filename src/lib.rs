@@ -1,33 +1,60 @@
 #![cfg_attr(feature = "cargo-clippy", allow(cast_ptr_alignment))]
 extern crate avro_rs;
 extern crate failure;
+extern crate num_bigint;
+extern crate num_traits;
+extern crate rand;
+extern crate rmpv;
 extern crate serde;
 extern crate serde_pickle;
+extern crate serde_json;
 
 #[macro_use]
 mod utils;
 
+mod arrow;
 mod avro_utils;
+mod binary;
 mod codec;
+mod compare;
+mod container;
 mod core;
+mod diff;
+mod encode;
+mod msgpack;
+mod options;
+mod path;
+mod random;
 mod reader;
 mod schema;
 mod types;
+mod visit;
 mod writer;
 
+pub use arrow::*;
 pub use codec::*;
+pub use compare::*;
 pub use core::*;
+pub use diff::*;
+pub use msgpack::*;
+pub use options::*;
+pub use path::*;
+pub use random::*;
 pub use reader::*;
 pub use schema::*;
 pub use types::*;
+pub use visit::*;
 pub use writer::*;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_pickle::value::Value as PickleValue;
-    use std::collections::HashMap;
+    use avro_rs::schema::Schema;
+    use avro_rs::types::Value;
+    use serde_pickle::value::{HashableValue, Value as PickleValue};
+    use std::collections::{BTreeMap, HashMap};
     use std::ffi::CString;
+    use std::mem;
     use std::ptr;
 
     #[test]
@@ -111,4 +138,704 @@ mod tests {
             avro_value_free(read_value);
         }
     }
+
+    #[test]
+    fn record_put_validation() {
+        unsafe {
+            let json = CString::new(r#"
+            {"namespace": "test", "type": "record", "name": "Test", "fields": [{"type": "long", "name": "field"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+            let record = types::avro_record_new(schema);
+
+            let field_str = CString::new("field").unwrap();
+            let field = core::avro_str_from_c_str(field_str.as_ptr());
+            let unknown_str = CString::new("unknown").unwrap();
+            let unknown = core::avro_str_from_c_str(unknown_str.as_ptr());
+
+            types::avro_record_put(record, &unknown, types::avro_value_long_new(1));
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            types::avro_record_put(record, &field, types::avro_value_boolean_new(1));
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            types::avro_record_put(record, &field, types::avro_value_int_new(42));
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::NoError as u32);
+            let value = types::avro_record_get(record, &field);
+            assert_eq!(types::avro_value_long_get(value), 42);
+
+            avro_record_free(record);
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn union_schema_new() {
+        unsafe {
+            let json = CString::new(r#"["null", "long"]"#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let value = types::avro_value_union_schema_new(schema, types::avro_value_int_new(42));
+            assert_eq!(
+                *(value as *const Value),
+                Value::Union(Some(Box::new(Value::Long(42))))
+            );
+            avro_value_free(value);
+
+            let null = types::avro_value_union_schema_new(schema, ptr::null_mut());
+            assert_eq!(*(null as *const Value), Value::Union(None));
+            avro_value_free(null);
+
+            let mismatch =
+                types::avro_value_union_schema_new(schema, types::avro_value_boolean_new(1));
+            assert!(mismatch.is_null());
+            core::avro_err_clear();
+
+            let name_str = CString::new("string").unwrap();
+            let name = core::avro_str_from_c_str(name_str.as_ptr());
+            let wrong_branch =
+                types::avro_value_union_named_new(schema, &name, types::avro_value_long_new(42));
+            assert!(wrong_branch.is_null());
+            core::avro_err_clear();
+
+            let null_str = CString::new("null").unwrap();
+            let null_name = core::avro_str_from_c_str(null_str.as_ptr());
+            let named_null =
+                types::avro_value_union_named_new(schema, &null_name, types::avro_value_null_new());
+            assert_eq!(*(named_null as *const Value), Value::Union(None));
+            avro_value_free(named_null);
+
+            let branch =
+                types::avro_value_union_branch_new(schema, 1, types::avro_value_int_new(7));
+            assert_eq!(
+                *(branch as *const Value),
+                Value::Union(Some(Box::new(Value::Long(7))))
+            );
+            avro_value_free(branch);
+
+            let null_branch = types::avro_value_union_branch_new(schema, 0, ptr::null_mut());
+            assert_eq!(*(null_branch as *const Value), Value::Union(None));
+            avro_value_free(null_branch);
+
+            let out_of_range =
+                types::avro_value_union_branch_new(schema, 2, types::avro_value_long_new(7));
+            assert!(out_of_range.is_null());
+            core::avro_err_clear();
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn enum_symbol_new() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "enum", "name": "Suit", "symbols": ["diamonds", "spades", "clubs", "hearts"]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let symbol_str = CString::new("clubs").unwrap();
+            let symbol = core::avro_str_from_c_str(symbol_str.as_ptr());
+            let value = types::avro_value_enum_symbol_new(schema, symbol);
+            assert_eq!(*(value as *const Value), Value::Enum(2, "clubs".to_owned()));
+            avro_value_free(value);
+
+            let unknown_str = CString::new("stars").unwrap();
+            let unknown = core::avro_str_from_c_str(unknown_str.as_ptr());
+            let unknown = types::avro_value_enum_symbol_new(schema, unknown);
+            assert!(unknown.is_null());
+            let message = core::avro_err_get_last_message();
+            assert!(message.as_str().contains("symbol `stars` is not in enum `Suit`"));
+            core::avro_err_clear();
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn array_buffers() {
+        unsafe {
+            let longs = [1i64, -2, 3];
+            let array = types::avro_value_array_from_longs(longs.as_ptr(), longs.len());
+            assert_eq!(
+                *(array as *const Value),
+                Value::Array(vec![Value::Long(1), Value::Long(-2), Value::Long(3)])
+            );
+            assert_eq!(types::avro_value_array_len(array), 3);
+
+            let mut out = [0i64; 4];
+            let n = types::avro_value_array_to_longs(array, out.as_mut_ptr(), out.len());
+            assert_eq!(&out[..n], &longs[..]);
+
+            let mut short = [0i64; 2];
+            assert_eq!(types::avro_value_array_to_longs(array, short.as_mut_ptr(), 2), 0);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            let mut doubles = [0f64; 4];
+            types::avro_value_array_to_doubles(array, doubles.as_mut_ptr(), doubles.len());
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            let bytes = [0u8, 1, 2];
+            let bools = types::avro_value_array_from_bools(bytes.as_ptr(), bytes.len());
+            assert_eq!(
+                *(bools as *const Value),
+                Value::Array(vec![
+                    Value::Boolean(false),
+                    Value::Boolean(true),
+                    Value::Boolean(true),
+                ])
+            );
+            let mut out = [0xffu8; 3];
+            assert_eq!(types::avro_value_array_to_bools(bools, out.as_mut_ptr(), 3), 3);
+            assert_eq!(out, [0, 1, 1]);
+            avro_value_free(bools);
+
+            let empty = types::avro_value_array_from_bools(ptr::null(), 0);
+            assert_eq!(*(empty as *const Value), Value::Array(vec![]));
+            avro_value_free(empty);
+            avro_value_free(array);
+        }
+    }
+
+    #[test]
+    fn deterministic_writer() {
+        unsafe {
+            let json = CString::new(r#"{"type": "map", "values": "long"}"#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let write = |keys: &[&str]| {
+                let mut items = HashMap::with_capacity(keys.len());
+                for key in keys {
+                    items.insert(key.to_string(), Value::Long(key.len() as i64));
+                }
+                let value = Box::into_raw(Box::new(Value::Map(items))) as *mut AvroValue;
+                let datum = writer::avro_to_avro_datum_deterministic(schema, value);
+
+                let writer = writer::avro_writer_new_deterministic(schema, AvroCodec::Null);
+                writer::avro_writer_append_ref(writer, value);
+                writer::avro_writer_flush(writer);
+                let data = writer::avro_writer_into_data(writer);
+                avro_value_free(value);
+                (datum.as_slice().to_vec(), data.as_slice().to_vec())
+            };
+
+            let keys = ["a", "bb", "ccc", "dddd", "eeeee", "ffffff", "g", "hh"];
+            let mut reversed = keys.to_vec();
+            reversed.reverse();
+            assert_eq!(write(&keys), write(&reversed));
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn record_get_by_index() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"type": "long", "name": "id"}, {"type": "string", "name": "name"}
+            ]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let name_str = CString::new("name").unwrap();
+            let name = core::avro_str_from_c_str(name_str.as_ptr());
+            let index = schema::avro_schema_record_field_index(schema, &name);
+            assert_eq!(index, 1);
+
+            let record = Box::into_raw(Box::new(Value::Record(vec![
+                ("id".to_owned(), Value::Long(1)),
+                ("name".to_owned(), Value::String("foo".to_owned())),
+            ]))) as *mut AvroValue;
+            assert_eq!(types::avro_value_record_field_count(record), 2);
+            assert_eq!(types::avro_value_record_field_name(record, index).as_str(), "name");
+            let value = types::avro_value_record_get_by_index(record, index);
+            assert_eq!(*(value as *const Value), Value::String("foo".to_owned()));
+
+            assert!(types::avro_value_record_get_by_index(record, 2).is_null());
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            avro_value_free(record);
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn record_get_or_default() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"type": ["null", "long"], "name": "id"},
+                {"type": ["null", "string"], "name": "comment"},
+                {"type": "string", "name": "name", "default": "anonymous"},
+                {"type": "long", "name": "age"}
+            ]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+            let record = Box::into_raw(Box::new(Value::Record(vec![(
+                "id".to_owned(),
+                Value::Union(Some(Box::new(Value::Long(42)))),
+            )]))) as *mut AvroValue;
+            let field = |name: &str| {
+                let name = CString::new(name).unwrap();
+                core::avro_str_from_c_str(name.as_ptr())
+            };
+
+            let id = types::avro_value_record_get_unwrapped(record, &field("id"));
+            assert_eq!(*(id as *const Value), Value::Long(42));
+            assert!(types::avro_value_record_get_unwrapped(record, &field("comment")).is_null());
+            core::avro_err_clear();
+
+            let comment = types::avro_value_record_get_or_default(record, &field("comment"), schema);
+            assert_eq!(*(comment as *const Value), Value::Null);
+            let name = types::avro_value_record_get_or_default(record, &field("name"), schema);
+            assert_eq!(*(name as *const Value), Value::String("anonymous".to_owned()));
+            let age = types::avro_value_record_get_or_default(record, &field("age"), schema);
+            assert!(age.is_null());
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+
+            avro_value_free(comment);
+            avro_value_free(name);
+            avro_value_free(record);
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn conversion_options() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}}
+            ]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+            let options = AvroConversionOptions {
+                enum_mode: AvroEnumMode::Index as i32,
+                ..avro_conversion_options_default()
+            };
+
+            let writer = writer::avro_writer_new_with_options(schema, AvroCodec::Null, &options);
+            let mut record = HashMap::new();
+            record.insert("suit".to_owned(), 1);
+            let pickle = serde_pickle::to_vec(&record, true).unwrap();
+            writer::avro_writer_append(writer, &core::AvroByteArray::from_vec_u8(pickle));
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::NoError as u32);
+
+            let read = |options: *const AvroConversionOptions| {
+                let reader = reader::avro_reader_new_with_options(&data, ptr::null(), options);
+                let value = reader::avro_reader_read_next(reader);
+                let value = serde_pickle::from_slice::<PickleValue>(value.as_slice()).unwrap();
+                avro_reader_free(reader);
+                value
+            };
+            let mut by_index = BTreeMap::new();
+            by_index.insert(HashableValue::String("suit".to_owned()), PickleValue::I64(1));
+            assert_eq!(read(&options), PickleValue::Dict(by_index));
+            let mut by_symbol = BTreeMap::new();
+            by_symbol.insert(
+                HashableValue::String("suit".to_owned()),
+                PickleValue::String("hearts".to_owned()),
+            );
+            assert_eq!(read(ptr::null()), PickleValue::Dict(by_symbol));
+
+            let invalid = [
+                AvroConversionOptions {
+                    strict: 2,
+                    ..avro_conversion_options_default()
+                },
+                AvroConversionOptions {
+                    record_mode: 2,
+                    ..avro_conversion_options_default()
+                },
+            ];
+            for options in &invalid {
+                let writer = writer::avro_writer_new_with_options(schema, AvroCodec::Null, options);
+                assert!(writer.is_null());
+                assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            }
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn writer_append_batch() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "id", "type": "long"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let record = |id: PickleValue| {
+                let mut record = BTreeMap::new();
+                record.insert(HashableValue::String("id".to_owned()), id);
+                PickleValue::Dict(record)
+            };
+            let values = PickleValue::List(vec![
+                record(PickleValue::I64(1)),
+                record(PickleValue::String("2".to_owned())),
+                record(PickleValue::I64(3)),
+            ]);
+            let pickle = serde_pickle::value_to_vec(&values, true).unwrap();
+            let values = core::AvroByteArray::from_vec_u8(pickle);
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            assert_eq!(writer::avro_writer_append_batch(writer, &values, ptr::null_mut()), 0);
+            let message = core::avro_err_get_last_message();
+            assert!(message
+                .as_str()
+                .starts_with("expected long at `[1].id`, got Python str"));
+            core::avro_err_clear();
+
+            let mut errors = core::AvroByteArray::default();
+            assert_eq!(writer::avro_writer_append_batch(writer, &values, &mut errors), 2);
+            let errors = serde_pickle::from_slice::<Vec<(usize, String)>>(errors.as_slice());
+            assert_eq!(
+                errors.unwrap(),
+                vec![(1, "expected long at `[1].id`, got Python str".to_owned())]
+            );
+
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+            let reader = reader::avro_reader_new(&data, None);
+            let mut n = 0;
+            loop {
+                let value = reader::avro_reader_read_next2(reader);
+                if value.is_null() {
+                    break
+                }
+                avro_value_free(value);
+                n += 1;
+            }
+            assert_eq!(n, 2);
+
+            avro_reader_free(reader);
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn reader_read_batch() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "id", "type": "long"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            random::avro_writer_append_random(writer, 42, ptr::null(), 5);
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let mut lens = Vec::new();
+            for _ in 0..4 {
+                let batch = reader::avro_reader_read_batch(reader, 2);
+                let batch = serde_pickle::from_slice::<Vec<PickleValue>>(batch.as_slice());
+                lens.push(batch.unwrap().len());
+            }
+            assert_eq!(lens, vec![2, 2, 1, 0]);
+            avro_reader_free(reader);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let first = reader::avro_reader_read_batch2(reader, 3);
+            assert_eq!(types::avro_value_array_len(first), 3);
+            let rest = reader::avro_reader_read_all(reader);
+            let rest = serde_pickle::from_slice::<Vec<PickleValue>>(rest.as_slice());
+            assert_eq!(rest.unwrap().len(), 2);
+            avro_value_free(first);
+            avro_reader_free(reader);
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn msgpack_writer_reader() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "id", "type": "long"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            // {"id": 42}
+            let record = vec![0x81, 0xa2, b'i', b'd', 0x2a];
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            let value = core::AvroByteArray::from_vec_u8(record.clone());
+            msgpack::avro_writer_append_msgpack(writer, &value);
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::NoError as u32);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let read = msgpack::avro_reader_read_next_msgpack(reader);
+            assert_eq!(read.as_slice(), &record[..]);
+            assert_eq!(msgpack::avro_reader_read_next_msgpack(reader).len, 0);
+            avro_reader_free(reader);
+
+            let datum = core::AvroByteArray::from_vec_u8(vec![0x54]);
+            let read = msgpack::avro_from_avro_datum_msgpack(&datum, schema, ptr::null());
+            assert_eq!(read.as_slice(), &record[..]);
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn reader_read_arrow() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "id", "type": "long"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            random::avro_writer_append_random(writer, 42, ptr::null(), 5);
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let mut arrow_schema = mem::zeroed::<arrow::ArrowSchema>();
+            arrow::avro_reader_arrow_schema(reader, &mut arrow_schema);
+            assert_eq!(arrow_schema.n_children, 1);
+            (arrow_schema.release.unwrap())(&mut arrow_schema);
+
+            let mut lens = Vec::new();
+            for _ in 0..4 {
+                let mut arrow_schema = mem::zeroed::<arrow::ArrowSchema>();
+                let mut array = mem::zeroed::<arrow::ArrowArray>();
+                let len = arrow::avro_reader_read_arrow(reader, 2, &mut arrow_schema, &mut array);
+                if len > 0 {
+                    assert_eq!(array.length, len as i64);
+                    (arrow_schema.release.unwrap())(&mut arrow_schema);
+                    (array.release.unwrap())(&mut array);
+                } else {
+                    assert!(array.release.is_none());
+                }
+                lens.push(len);
+            }
+            assert_eq!(lens, vec![2, 2, 1, 0]);
+            avro_reader_free(reader);
+
+            let json = CString::new(r#""long""#).unwrap();
+            let long_json = core::avro_str_from_c_str(json.as_ptr());
+            let long_schema = schema::avro_schema_from_json(&long_json);
+            let writer = writer::avro_writer_new(long_schema, AvroCodec::Null);
+            random::avro_writer_append_random(writer, 42, ptr::null(), 1);
+            writer::avro_writer_flush(writer);
+            let long_data = writer::avro_writer_into_data(writer);
+            let reader = reader::avro_reader_new(&long_data, None);
+            let mut array = mem::zeroed::<arrow::ArrowArray>();
+            let len = arrow::avro_reader_read_arrow(reader, 2, &mut mem::zeroed(), &mut array);
+            assert_eq!(len, 0);
+            let message = core::avro_err_get_last_message();
+            assert!(message.as_str().starts_with("only records"));
+            core::avro_err_clear();
+            avro_reader_free(reader);
+            avro_schema_free(long_schema);
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn writer_append_arrow() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string", "default": "anonymous"}
+            ]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let ids = Schema::parse_str(r#"
+            {"type": "record", "name": "Ids", "fields": [{"name": "id", "type": "long"}]}
+            "#).unwrap();
+            let field = arrow::ArrowField::record_batch(&ids).unwrap();
+            let mut column = arrow::Column::new(&field.data_type);
+            for id in 0..3 {
+                let record = Value::Record(vec![("id".to_owned(), Value::Long(id))]);
+                column.append(&field, &record).unwrap();
+            }
+            let mut arrow_schema = arrow::export_schema(&field);
+            let mut array = arrow::export_array(&field, column);
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            let appended = arrow::avro_writer_append_arrow(writer, &mut arrow_schema, &mut array);
+            assert_eq!(appended, 3);
+            assert!(arrow_schema.release.is_none() && array.release.is_none());
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let values = reader::avro_reader_read_all(reader);
+            let values = serde_pickle::from_slice::<Vec<PickleValue>>(values.as_slice()).unwrap();
+            assert_eq!(values.len(), 3);
+            let mut expected = BTreeMap::new();
+            expected.insert(
+                HashableValue::String("id".to_owned()),
+                PickleValue::I64(2),
+            );
+            expected.insert(
+                HashableValue::String("name".to_owned()),
+                PickleValue::String("anonymous".to_owned()),
+            );
+            assert_eq!(values[2], PickleValue::Dict(expected));
+            avro_reader_free(reader);
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn default_writer_read_by_avro_rs() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [
+                {"name": "tags", "type": {"type": "array", "items": "string"}},
+                {"name": "counts", "type": {"type": "map", "values": "long"}}
+            ]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            // avro-rs 0.4 writes empty arrays and maps with an extra zero its reader chokes on,
+            // which the default writer keeps doing for compatibility
+            let values = (0..3)
+                .map(|n| {
+                    let mut counts = HashMap::new();
+                    counts.insert(n.to_string(), Value::Long(n));
+                    Value::Record(vec![
+                        ("tags".to_owned(), Value::Array(vec![Value::String(n.to_string())])),
+                        ("counts".to_owned(), Value::Map(counts)),
+                    ])
+                })
+                .collect::<Vec<_>>();
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Deflate);
+            for value in &values {
+                writer::avro_writer_append_ref(writer, value as *const Value as *const AvroValue);
+            }
+            writer::avro_writer_flush(writer);
+            let data = writer::avro_writer_into_data(writer);
+
+            let reader = avro_rs::Reader::new(data.as_slice()).unwrap();
+            let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(read, values);
+
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn to_avro_datum_variants() {
+        unsafe {
+            let json = CString::new(r#"{"type": "array", "items": "long"}"#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let datums = |schema: *const AvroSchema, value: Value| {
+                let value = Box::into_raw(Box::new(value)) as *mut AvroValue;
+                let borrowed = writer::avro_to_avro_datum_ref(schema, value);
+                let size = writer::avro_value_encoded_size(schema, value);
+                let owned = writer::avro_to_avro_datum(schema, value);
+                (owned.as_slice().to_vec(), borrowed.as_slice().to_vec(), size)
+            };
+
+            let (owned, borrowed, size) =
+                datums(schema, Value::Array(vec![Value::Long(1), Value::Long(2)]));
+            assert_eq!(owned, vec![4, 2, 4, 0]);
+            assert_eq!(borrowed, owned);
+            assert_eq!(size, owned.len());
+
+            let (owned, borrowed, size) = datums(schema, Value::Array(vec![]));
+            assert_eq!(owned, vec![0, 0]);
+            assert_eq!(borrowed, owned);
+            assert_eq!(size, owned.len());
+
+            let json = CString::new(r#"{"type": "map", "values": "long"}"#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let map_schema = schema::avro_schema_from_json(&avro_json);
+            let (owned, borrowed, size) = datums(map_schema, Value::Map(HashMap::new()));
+            assert_eq!(owned, vec![0, 0]);
+            assert_eq!(borrowed, owned);
+            assert_eq!(size, owned.len());
+
+            avro_schema_free(map_schema);
+            avro_schema_free(schema);
+        }
+    }
+
+    #[test]
+    fn reader_read_batch_error() {
+        unsafe {
+            let json = CString::new(r#"
+            {"type": "record", "name": "Test", "fields": [{"name": "name", "type": "string"}]}
+            "#).unwrap();
+            let avro_json = core::avro_str_from_c_str(json.as_ptr());
+            let schema = schema::avro_schema_from_json(&avro_json);
+
+            let writer = writer::avro_writer_new(schema, AvroCodec::Null);
+            for name in &["a", "b", "c", "d"] {
+                let mut record = HashMap::new();
+                record.insert("name", name);
+                let pickle = serde_pickle::to_vec(&record, true).unwrap();
+                writer::avro_writer_append(writer, &core::AvroByteArray::from_vec_u8(pickle));
+            }
+            writer::avro_writer_flush(writer);
+            let mut data = writer::avro_writer_into_data(writer).as_slice().to_vec();
+            // the length of "c" goes past the end of the block
+            let c = data.windows(2).position(|bytes| bytes == [2, b'c']).unwrap();
+            data[c] = 0x7e;
+            let data = core::AvroByteArray::from_vec_u8(data);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let batch = reader::avro_reader_read_batch(reader, 10);
+            let batch = serde_pickle::from_slice::<Vec<PickleValue>>(batch.as_slice());
+            assert_eq!(batch.unwrap().len(), 2);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::NoError as u32);
+            assert_eq!(reader::avro_reader_read_batch(reader, 10).len, 0);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+            avro_reader_free(reader);
+
+            let reader = reader::avro_reader_new(&data, None);
+            let mut arrow_schema = mem::zeroed::<arrow::ArrowSchema>();
+            let mut array = mem::zeroed::<arrow::ArrowArray>();
+            let len = arrow::avro_reader_read_arrow(reader, 10, &mut arrow_schema, &mut array);
+            assert_eq!(len, 2);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::NoError as u32);
+            (arrow_schema.release.unwrap())(&mut arrow_schema);
+            (array.release.unwrap())(&mut array);
+            let len = arrow::avro_reader_read_arrow(reader, 10, &mut mem::zeroed(), &mut array);
+            assert_eq!(len, 0);
+            assert_eq!(core::avro_err_get_last_code() as u32, AvroErrorCode::Unknown as u32);
+            core::avro_err_clear();
+            avro_reader_free(reader);
+
+            avro_schema_free(schema);
+        }
+    }
 }
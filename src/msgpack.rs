@@ -0,0 +1,327 @@
+//! MessagePack as an alternative interchange format to pickle.
+//!
+//! MessagePack values are converted to avro values with the same rules as pickled values, and
+//! avro values are converted to MessagePack values with the same representations.
+use avro_rs::from_avro_datum;
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use avro_utils::{avro_value_from_pickle_with, PickleConversionError};
+use core::AvroByteArray;
+use failure::{err_msg, Error};
+use num_bigint::BigInt;
+use options::{
+    conversion_options, AvroConversionOptions, AvroEnumMode, AvroRecordMode, ConversionOptions,
+};
+use reader::{AvroReader, ReaderHandle};
+use rmpv;
+use rmpv::Value as MsgpackValue;
+use schema::AvroSchema;
+use serde_pickle::value::{HashableValue, Value as PickleValue};
+use writer::{AvroWriter, WriterHandle};
+
+/// Name of the MessagePack type a pickled value was converted from, given the name of its
+/// Python type.
+fn msgpack_type_name(python_type: &str) -> &'static str {
+    match python_type {
+        "NoneType" => "nil",
+        "bool" => "bool",
+        "int" => "int",
+        "float" => "float",
+        "bytes" => "bin",
+        "str" => "str",
+        "dict" => "map",
+        _ => "array",
+    }
+}
+
+fn pickle_int(n: &rmpv::Integer) -> PickleValue {
+    match n.as_i64() {
+        Some(n) => PickleValue::I64(n),
+        None => PickleValue::Int(BigInt::from(n.as_u64().unwrap_or(0))),
+    }
+}
+
+/// Convert a MessagePack value to the pickled value a Python caller would have given.
+fn pickle_from_msgpack(value: MsgpackValue) -> Result<PickleValue, Error> {
+    Ok(match value {
+        MsgpackValue::Nil => PickleValue::None,
+        MsgpackValue::Boolean(b) => PickleValue::Bool(b),
+        MsgpackValue::Integer(ref n) => pickle_int(n),
+        MsgpackValue::F32(x) => PickleValue::F64(f64::from(x)),
+        MsgpackValue::F64(x) => PickleValue::F64(x),
+        // strings which are not valid utf-8 are kept as bytes
+        MsgpackValue::String(s) => match s.as_str() {
+            Some(_) => PickleValue::String(s.into_str().unwrap_or_default()),
+            None => PickleValue::Bytes(s.into_bytes()),
+        },
+        MsgpackValue::Binary(bytes) => PickleValue::Bytes(bytes),
+        MsgpackValue::Array(values) => PickleValue::List(
+            values
+                .into_iter()
+                .map(pickle_from_msgpack)
+                .collect::<Result<_, _>>()?,
+        ),
+        MsgpackValue::Map(entries) => PickleValue::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| Ok((pickle_key(key)?, pickle_from_msgpack(value)?)))
+                .collect::<Result<_, Error>>()?,
+        ),
+        MsgpackValue::Ext(kind, _) => {
+            return Err(err_msg(format!(
+                "MessagePack extension type {} is not supported",
+                kind
+            )))
+        },
+    })
+}
+
+fn pickle_key(key: MsgpackValue) -> Result<HashableValue, Error> {
+    Ok(match key {
+        MsgpackValue::Nil => HashableValue::None,
+        MsgpackValue::Boolean(b) => HashableValue::Bool(b),
+        MsgpackValue::Integer(ref n) => match pickle_int(n) {
+            PickleValue::I64(n) => HashableValue::I64(n),
+            PickleValue::Int(n) => HashableValue::Int(n),
+            _ => unreachable!(),
+        },
+        MsgpackValue::F32(x) => HashableValue::F64(f64::from(x)),
+        MsgpackValue::F64(x) => HashableValue::F64(x),
+        MsgpackValue::String(s) => match s.as_str() {
+            Some(_) => HashableValue::String(s.into_str().unwrap_or_default()),
+            None => HashableValue::Bytes(s.into_bytes()),
+        },
+        MsgpackValue::Binary(bytes) => HashableValue::Bytes(bytes),
+        MsgpackValue::Array(values) => HashableValue::Tuple(
+            values
+                .into_iter()
+                .map(pickle_key)
+                .collect::<Result<_, _>>()?,
+        ),
+        MsgpackValue::Map(_) | MsgpackValue::Ext(..) => {
+            return Err(err_msg(
+                "MessagePack maps and extension types cannot be map keys",
+            ))
+        },
+    })
+}
+
+/// Convert a MessagePack-encoded value to an avro value of a schema.
+///
+/// Errors give the path of the offending value along with the expected schema and the
+/// MessagePack type found.
+pub fn avro_value_from_msgpack(
+    schema: &Schema,
+    bytes: &[u8],
+    options: &ConversionOptions,
+) -> Result<Value, Error> {
+    let mut reader = bytes;
+    let value = rmpv::decode::read_value(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(err_msg(format!(
+            "{} trailing bytes after the MessagePack value",
+            reader.len()
+        )))
+    }
+
+    avro_value_from_pickle_with(schema, pickle_from_msgpack(value)?, options).map_err(|err| {
+        match err.downcast::<PickleConversionError>() {
+            Ok(err) => {
+                let found = format!("MessagePack {}", msgpack_type_name(err.python_type));
+                err_msg(err.describe(&found))
+            },
+            Err(err) => err,
+        }
+    })
+}
+
+/// Convert an avro value to a MessagePack value, with the representations of the conversion
+/// options.
+pub fn msgpack_from_avro(value: &Value, options: &ConversionOptions) -> MsgpackValue {
+    let convert = |value| msgpack_from_avro(value, options);
+    match *value {
+        Value::Null | Value::Union(None) => MsgpackValue::Nil,
+        Value::Boolean(b) => MsgpackValue::Boolean(b),
+        Value::Int(n) => MsgpackValue::from(i64::from(n)),
+        Value::Long(n) => MsgpackValue::from(n),
+        Value::Float(x) => MsgpackValue::F32(x),
+        Value::Double(x) => MsgpackValue::F64(x),
+        Value::Bytes(ref bytes) | Value::Fixed(_, ref bytes) => MsgpackValue::Binary(bytes.clone()),
+        Value::String(ref s) => MsgpackValue::from(s.clone()),
+        Value::Array(ref values) => MsgpackValue::Array(values.iter().map(convert).collect()),
+        Value::Map(ref values) => {
+            // entries are sorted for the same value to always give the same bytes
+            let mut entries = values.iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            MsgpackValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (MsgpackValue::from(key.clone()), convert(value)))
+                    .collect(),
+            )
+        },
+        Value::Union(Some(ref value)) => convert(value),
+        Value::Record(ref fields) => match options.record_mode {
+            AvroRecordMode::Dict => MsgpackValue::Map(
+                fields
+                    .iter()
+                    .map(|(name, value)| (MsgpackValue::from(name.clone()), convert(value)))
+                    .collect(),
+            ),
+            AvroRecordMode::Tuple => {
+                MsgpackValue::Array(fields.iter().map(|(_, value)| convert(value)).collect())
+            },
+        },
+        Value::Enum(index, ref symbol) => match options.enum_mode {
+            AvroEnumMode::Symbol => MsgpackValue::from(symbol.clone()),
+            AvroEnumMode::Index => MsgpackValue::from(i64::from(index)),
+        },
+    }
+}
+
+/// Encode an avro value as MessagePack, with the representations of the conversion options.
+pub fn msgpack_bytes_from_avro(
+    value: &Value,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &msgpack_from_avro(value, options))?;
+    Ok(bytes)
+}
+
+ffi_fn! {
+    /// Append a MessagePack-encoded avro value to an avro writer, converted like a pickled value
+    /// given to `avro_writer_append`. Writing is not necessarily happening here. Call
+    /// `avro_writer_flush` to force an actual write.
+    unsafe fn avro_writer_append_msgpack(
+        writer: *mut AvroWriter,
+        value: *const AvroByteArray
+    ) -> Result<usize> {
+        let WriterHandle { writer, options } = &mut *(writer as *mut WriterHandle);
+        let value = avro_value_from_msgpack(writer.schema(), (&*value).as_slice(), options)?;
+        writer.append_value_ref(&value)
+    }
+}
+
+ffi_fn! {
+    /// Read the next value out of an avro reader, encoded as MessagePack. The byte array is
+    /// empty once all the values are read.
+    unsafe fn avro_reader_read_next_msgpack(reader: *mut AvroReader) -> Result<AvroByteArray> {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        match handle.next_value() {
+            None => Ok(AvroByteArray::default()),
+            Some(v) => {
+                let value = msgpack_bytes_from_avro(&v?, &handle.options)?;
+                Ok(AvroByteArray::from_vec_u8(value))
+            },
+        }
+    }
+}
+
+ffi_fn! {
+    /// Read avro serialized data into a MessagePack-encoded value, converted with the given
+    /// options. Default options are used if `options` is null.
+    unsafe fn avro_from_avro_datum_msgpack(
+        buffer: *const AvroByteArray,
+        schema: *const AvroSchema,
+        options: *const AvroConversionOptions
+    ) -> Result<AvroByteArray> {
+        let mut reader = (&*buffer).as_slice();
+        let schema = &*(schema as *const Schema);
+        let options = conversion_options(options)?;
+        let value = from_avro_datum(schema, &mut reader, None)?;
+        Ok(AvroByteArray::from_vec_u8(msgpack_bytes_from_avro(&value, &options)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema::parse_str(
+            r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "tags", "type": {"type": "array", "items": "string"}},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "ratio", "type": ["null", "double"]}
+        ]}
+        "#,
+        )
+        .unwrap()
+    }
+
+    fn encode(value: &MsgpackValue) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, value).unwrap();
+        bytes
+    }
+
+    fn record(id: MsgpackValue) -> MsgpackValue {
+        MsgpackValue::Map(vec![
+            (MsgpackValue::from("id"), id),
+            (
+                MsgpackValue::from("tags"),
+                MsgpackValue::Array(vec![MsgpackValue::from("a"), MsgpackValue::from("b")]),
+            ),
+            (MsgpackValue::from("suit"), MsgpackValue::from("hearts")),
+            (MsgpackValue::from("ratio"), MsgpackValue::from(2)),
+        ])
+    }
+
+    #[test]
+    fn msgpack_round_trip() {
+        let schema = schema();
+        let options = ConversionOptions::default();
+        let bytes = encode(&record(MsgpackValue::from(42)));
+
+        let value = avro_value_from_msgpack(&schema, &bytes, &options).unwrap();
+        assert_eq!(
+            value,
+            Value::Record(vec![
+                ("id".to_owned(), Value::Long(42)),
+                (
+                    "tags".to_owned(),
+                    Value::Array(vec![
+                        Value::String("a".to_owned()),
+                        Value::String("b".to_owned()),
+                    ]),
+                ),
+                ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+                (
+                    "ratio".to_owned(),
+                    Value::Union(Some(Box::new(Value::Double(2.0))))
+                ),
+            ])
+        );
+
+        let expected = MsgpackValue::Map(vec![
+            (MsgpackValue::from("id"), MsgpackValue::from(42)),
+            (
+                MsgpackValue::from("tags"),
+                MsgpackValue::Array(vec![MsgpackValue::from("a"), MsgpackValue::from("b")]),
+            ),
+            (MsgpackValue::from("suit"), MsgpackValue::from("hearts")),
+            (MsgpackValue::from("ratio"), MsgpackValue::F64(2.0)),
+        ]);
+        assert_eq!(msgpack_from_avro(&value, &options), expected);
+    }
+
+    #[test]
+    fn msgpack_errors() {
+        let schema = schema();
+        let options = ConversionOptions::default();
+
+        let bytes = encode(&record(MsgpackValue::from("42")));
+        let err = avro_value_from_msgpack(&schema, &bytes, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected long at `id`, got MessagePack str"
+        );
+
+        let mut bytes = encode(&record(MsgpackValue::from(42)));
+        bytes.push(0);
+        assert!(avro_value_from_msgpack(&schema, &bytes, &options).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Options controlling the conversion of pickled values to and from avro values.
+use failure::{err_msg, Error};
+
+/// Representation of enum values in pickled values.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AvroEnumMode {
+    /// Enums are the str of their symbol.
+    Symbol,
+    /// Enums are the int index of their symbol.
+    Index,
+}
+
+impl AvroEnumMode {
+    fn from_i32(mode: i32) -> Result<AvroEnumMode, Error> {
+        match mode {
+            0 => Ok(AvroEnumMode::Symbol),
+            1 => Ok(AvroEnumMode::Index),
+            mode => Err(err_msg(format!("{} is not a valid enum mode", mode))),
+        }
+    }
+}
+
+/// Representation of records in pickled values read from avro data.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AvroRecordMode {
+    /// Records are dicts, with their fields in the order of the schema.
+    Dict,
+    /// Records are tuples of their field values, in the order of the schema.
+    Tuple,
+}
+
+impl AvroRecordMode {
+    fn from_i32(mode: i32) -> Result<AvroRecordMode, Error> {
+        match mode {
+            0 => Ok(AvroRecordMode::Dict),
+            1 => Ok(AvroRecordMode::Tuple),
+            mode => Err(err_msg(format!("{} is not a valid record mode", mode))),
+        }
+    }
+}
+
+/// Options controlling the conversion of pickled values to and from avro values, as given across
+/// the FFI boundary: flags are 0 or 1, and modes are values of `AvroEnumMode` and
+/// `AvroRecordMode`.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct AvroConversionOptions {
+    /// Only accept the Python type closest to each schema: ints for floats and doubles, sets and
+    /// tuples for arrays, bytes keys for records and enums in the other representation than
+    /// `enum_mode` are rejected.
+    pub strict: u8,
+    /// Accept bytes for strings and str for bytes.
+    pub str_bytes_interchange: u8,
+    /// Convert map keys which are not str (bytes, ints, floats, bools and None) to strings,
+    /// instead of rejecting them.
+    pub stringify_map_keys: u8,
+    /// Representation of enums, both accepted and produced, an `AvroEnumMode`.
+    pub enum_mode: i32,
+    /// Convert fields missing from a dict to null when their schema is a union with null, even
+    /// if they have no default.
+    pub missing_nullable_as_null: u8,
+    /// Reject dict keys which are not fields of the record, instead of ignoring them.
+    pub reject_extra_keys: u8,
+    /// Representation of records in pickled values read from avro data, an `AvroRecordMode`.
+    pub record_mode: i32,
+    /// Protocol of the pickled values read from avro data, 2 or 3.
+    pub pickle_protocol: u8,
+}
+
+/// Options controlling the conversion of pickled values to and from avro values, once checked.
+/// See `AvroConversionOptions` for the meaning of each of them.
+#[derive(Clone, Debug)]
+pub struct ConversionOptions {
+    pub strict: bool,
+    pub str_bytes_interchange: bool,
+    pub stringify_map_keys: bool,
+    pub enum_mode: AvroEnumMode,
+    pub missing_nullable_as_null: bool,
+    pub reject_extra_keys: bool,
+    pub record_mode: AvroRecordMode,
+    pub pickle_protocol: u8,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> ConversionOptions {
+        ConversionOptions {
+            strict: false,
+            str_bytes_interchange: true,
+            stringify_map_keys: false,
+            enum_mode: AvroEnumMode::Symbol,
+            missing_nullable_as_null: false,
+            reject_extra_keys: false,
+            record_mode: AvroRecordMode::Dict,
+            pickle_protocol: 2,
+        }
+    }
+}
+
+impl ConversionOptions {
+    /// Whether pickled values are written with protocol 3 rather than 2, the only supported
+    /// ones.
+    pub fn use_proto_3(&self) -> Result<bool, Error> {
+        match self.pickle_protocol {
+            2 => Ok(false),
+            3 => Ok(true),
+            protocol => Err(err_msg(format!(
+                "pickle protocol {} is not supported, only 2 and 3 are",
+                protocol
+            ))),
+        }
+    }
+}
+
+impl AvroConversionOptions {
+    fn check(&self) -> Result<ConversionOptions, Error> {
+        let flag = |name: &str, value: u8| match value {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(err_msg(format!(
+                "option `{}` must be 0 or 1, got {}",
+                name, value
+            ))),
+        };
+        let options = ConversionOptions {
+            strict: flag("strict", self.strict)?,
+            str_bytes_interchange: flag("str_bytes_interchange", self.str_bytes_interchange)?,
+            stringify_map_keys: flag("stringify_map_keys", self.stringify_map_keys)?,
+            enum_mode: AvroEnumMode::from_i32(self.enum_mode)?,
+            missing_nullable_as_null: flag(
+                "missing_nullable_as_null",
+                self.missing_nullable_as_null,
+            )?,
+            reject_extra_keys: flag("reject_extra_keys", self.reject_extra_keys)?,
+            record_mode: AvroRecordMode::from_i32(self.record_mode)?,
+            pickle_protocol: self.pickle_protocol,
+        };
+        options.use_proto_3()?;
+        Ok(options)
+    }
+}
+
+impl<'a> From<&'a ConversionOptions> for AvroConversionOptions {
+    fn from(options: &'a ConversionOptions) -> AvroConversionOptions {
+        AvroConversionOptions {
+            strict: options.strict as u8,
+            str_bytes_interchange: options.str_bytes_interchange as u8,
+            stringify_map_keys: options.stringify_map_keys as u8,
+            enum_mode: options.enum_mode as i32,
+            missing_nullable_as_null: options.missing_nullable_as_null as u8,
+            reject_extra_keys: options.reject_extra_keys as u8,
+            record_mode: options.record_mode as i32,
+            pickle_protocol: options.pickle_protocol,
+        }
+    }
+}
+
+/// Options given by pointer across the FFI boundary, the defaults if the pointer is null.
+///
+/// # Safety
+///
+/// `options` must be null or point to valid options.
+pub unsafe fn conversion_options(
+    options: *const AvroConversionOptions,
+) -> Result<ConversionOptions, Error> {
+    if options.is_null() {
+        return Ok(ConversionOptions::default())
+    }
+    (*options).check()
+}
+
+#[no_mangle]
+/// Default options for the conversion of pickled values: lenient, matching the conversion of
+/// writers and readers created without options.
+pub extern "C" fn avro_conversion_options_default() -> AvroConversionOptions {
+    AvroConversionOptions::from(&ConversionOptions::default())
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+
+use avro_rs::types::Value;
+use core::AvroStr;
+use failure::{err_msg, Error};
+use types::AvroValue;
+
+/// A step of a path into a nested avro value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    /// `.name`: a record field, or a map key.
+    Field(String),
+    /// `[3]`: an array index.
+    Index(usize),
+    /// `["key"]`: a map key.
+    Key(String),
+}
+
+/// A path into a nested avro value, e.g. `a.b[3].c` or `tags["env"]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path(pub Vec<PathSegment>);
+
+impl Path {
+    pub fn parse(path: &str) -> Result<Path, Error> {
+        let mut segments = Vec::new();
+        let mut chars = path.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '[' => {
+                    let segment = match chars.peek() {
+                        Some(&quote) if quote == '"' || quote == '\'' => {
+                            chars.next();
+                            let mut key = String::new();
+                            loop {
+                                match chars.next() {
+                                    Some('\\') => match chars.next() {
+                                        Some(escaped) => key.push(escaped),
+                                        None => return Err(err_msg("unterminated map key in path")),
+                                    },
+                                    Some(c) if c == quote => break,
+                                    Some(c) => key.push(c),
+                                    None => return Err(err_msg("unterminated map key in path")),
+                                }
+                            }
+                            PathSegment::Key(key)
+                        },
+                        _ => {
+                            let mut index = String::new();
+                            while let Some(&c) = chars.peek() {
+                                if c == ']' {
+                                    break
+                                }
+                                index.push(c);
+                                chars.next();
+                            }
+                            PathSegment::Index(index.trim().parse().map_err(|_| {
+                                err_msg(format!("invalid array index `{}` in path", index))
+                            })?)
+                        },
+                    };
+                    if chars.next() != Some(']') {
+                        return Err(err_msg("missing `]` in path"))
+                    }
+                    segments.push(segment);
+                },
+                '.' if segments.is_empty() => return Err(err_msg("path cannot start with `.`")),
+                _ => {
+                    let mut name = String::new();
+                    if c != '.' {
+                        name.push(c);
+                    }
+                    while let Some(&c) = chars.peek() {
+                        if c == '.' || c == '[' {
+                            break
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    if name.is_empty() {
+                        return Err(err_msg("empty field name in path"))
+                    }
+                    segments.push(PathSegment::Field(name));
+                },
+            }
+        }
+
+        Ok(Path(segments))
+    }
+
+    /// This path extended with one more segment.
+    pub fn child(&self, segment: PathSegment) -> Path {
+        let mut segments = self.0.clone();
+        segments.push(segment);
+        Path(segments)
+    }
+
+    /// Walk a value along this path. Non-null unions are stepped through transparently.
+    pub fn lookup<'a>(&self, mut value: &'a Value) -> Result<&'a Value, Error> {
+        for (depth, segment) in self.0.iter().enumerate() {
+            while let Value::Union(Some(ref inner)) = *value {
+                value = inner;
+            }
+
+            let found = match (segment, value) {
+                (PathSegment::Field(name), Value::Record(fields)) => fields
+                    .iter()
+                    .find(|field| field.0 == *name)
+                    .map(|field| &field.1),
+                (PathSegment::Field(key), Value::Map(items))
+                | (PathSegment::Key(key), Value::Map(items)) => items.get(key),
+                (PathSegment::Index(index), Value::Array(items)) => items.get(*index),
+                (_, Value::Union(None)) => {
+                    return Err(err_msg(format!(
+                        "`{}` is null",
+                        Path(self.0[..depth].to_vec())
+                    )))
+                },
+                _ => {
+                    return Err(err_msg(format!(
+                        "cannot apply `{}` to a {} at `{}`",
+                        Path(vec![segment.clone()]),
+                        value_kind(value),
+                        Path(self.0[..depth].to_vec())
+                    )))
+                },
+            };
+
+            value = found.ok_or_else(|| {
+                err_msg(format!(
+                    "`{}` not found",
+                    Path(self.0[..depth + 1].to_vec())
+                ))
+            })?;
+        }
+
+        Ok(value)
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match *segment {
+                PathSegment::Field(ref name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(ref name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+                PathSegment::Key(ref key) => {
+                    // only what `parse` unescapes is escaped, so that paths round-trip
+                    let key = key.replace('\\', "\\\\").replace('"', "\\\"");
+                    write!(f, "[\"{}\"]", key)?
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of the kind of a value, for error messages.
+pub fn value_kind(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Boolean(_) => "boolean",
+        Value::Int(_) => "int",
+        Value::Long(_) => "long",
+        Value::Float(_) => "float",
+        Value::Double(_) => "double",
+        Value::Bytes(_) => "bytes",
+        Value::String(_) => "string",
+        Value::Fixed(..) => "fixed",
+        Value::Enum(..) => "enum",
+        Value::Union(_) => "union",
+        Value::Array(_) => "array",
+        Value::Map(_) => "map",
+        Value::Record(_) => "record",
+    }
+}
+
+ffi_fn! {
+    /// Get a nested value following a path such as `a.b[3].c` or `tags["env"]`.
+    ///
+    /// Records, arrays, maps and non-null unions are walked through. The value is borrowed
+    /// from its parent.
+    unsafe fn avro_value_get_path(
+        value: *const AvroValue,
+        path: *const AvroStr
+    ) -> Result<*const AvroValue> {
+        let value = &*(value as *const Value);
+        let path = Path::parse((&*path).as_str())?;
+        Ok(path.lookup(value)? as *const Value as *const AvroValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn parse_path() {
+        let path = Path::parse(r#"a.b[3].c["en\"v"]"#).unwrap();
+        assert_eq!(
+            path.0,
+            vec![
+                PathSegment::Field("a".to_owned()),
+                PathSegment::Field("b".to_owned()),
+                PathSegment::Index(3),
+                PathSegment::Field("c".to_owned()),
+                PathSegment::Key("en\"v".to_owned()),
+            ]
+        );
+        assert_eq!(path.to_string(), r#"a.b[3].c["en\"v"]"#);
+        assert!(Path::parse("a[b]").is_err());
+        assert!(Path::parse("a..b").is_err());
+        assert!(Path::parse("a[\"b").is_err());
+    }
+
+    #[test]
+    fn path_round_trip() {
+        let path = Path(vec![
+            PathSegment::Field("tags".to_owned()),
+            PathSegment::Key("a\tb\n\"c\\d\u{1}".to_owned()),
+        ]);
+        assert_eq!(path.to_string(), "tags[\"a\tb\n\\\"c\\\\d\u{1}\"]");
+        assert_eq!(Path::parse(&path.to_string()).unwrap(), path);
+    }
+
+    #[test]
+    fn lookup_path() {
+        let mut tags = HashMap::new();
+        tags.insert("env".to_owned(), Value::String("prod".to_owned()));
+        let value = Value::Record(vec![
+            ("tags".to_owned(), Value::Map(tags)),
+            (
+                "items".to_owned(),
+                Value::Union(Some(Box::new(Value::Array(vec![
+                    Value::Record(vec![("sku".to_owned(), Value::Long(1))]),
+                    Value::Record(vec![("sku".to_owned(), Value::Long(2))]),
+                ])))),
+            ),
+        ]);
+
+        let lookup = |path| Path::parse(path).unwrap().lookup(&value).cloned();
+        assert_eq!(lookup("items[1].sku").unwrap(), Value::Long(2));
+        assert_eq!(
+            lookup(r#"tags["env"]"#).unwrap(),
+            Value::String("prod".to_owned())
+        );
+        assert_eq!(
+            lookup("tags.env").unwrap(),
+            Value::String("prod".to_owned())
+        );
+        assert!(lookup("items[2].sku").is_err());
+        assert!(lookup("tags[0]").is_err());
+    }
+}
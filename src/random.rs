@@ -0,0 +1,255 @@
+//! Generation of random avro values, reproducible from a seed.
+use std::collections::HashMap;
+use std::os::raw::c_double;
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use avro_utils::schema_name;
+use failure::{err_msg, Error};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use schema::AvroSchema;
+use types::AvroValue;
+use writer::{AvroWriter, WriterHandle};
+
+/// Options controlling the generation of random avro values.
+#[repr(C)]
+#[derive(Clone, Debug)]
+pub struct AvroRandomOptions {
+    /// Maximum number of items in an array.
+    pub max_array_len: usize,
+    /// Maximum number of entries in a map.
+    pub max_map_len: usize,
+    /// Maximum length of strings and bytes.
+    pub max_string_len: usize,
+    /// Maximum nesting of arrays, maps, unions and records. Past it, arrays and maps are
+    /// empty and unions are null.
+    pub max_depth: usize,
+    /// Probability (between 0 and 1) for a union to be null.
+    pub null_probability: c_double,
+}
+
+impl Default for AvroRandomOptions {
+    fn default() -> AvroRandomOptions {
+        AvroRandomOptions {
+            max_array_len: 8,
+            max_map_len: 8,
+            max_string_len: 16,
+            max_depth: 8,
+            null_probability: 0.1,
+        }
+    }
+}
+
+/// Generator of random avro values.
+pub struct RandomValues {
+    rng: XorShiftRng,
+    options: AvroRandomOptions,
+}
+
+impl RandomValues {
+    pub fn new(seed: u64, options: AvroRandomOptions) -> Result<RandomValues, Error> {
+        if !(options.null_probability >= 0.0 && options.null_probability <= 1.0) {
+            return Err(err_msg("null probability should be between 0 and 1"))
+        }
+        let (low, high) = (seed as u32, (seed >> 32) as u32);
+        // the constant avoids the all-zero seed XorShiftRng does not accept
+        let rng = XorShiftRng::from_seed([low, high, low ^ 0x9E37_79B9, high]);
+        Ok(RandomValues { rng, options })
+    }
+
+    fn len(&mut self, max: usize, depth: usize) -> usize {
+        if depth >= self.options.max_depth || max == 0 {
+            0
+        } else {
+            self.rng.gen_range(0, max + 1)
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; len];
+        self.rng.fill_bytes(&mut bytes);
+        bytes
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.len(self.options.max_string_len, 0);
+        self.rng.gen_ascii_chars().take(len).collect()
+    }
+
+    pub fn value(&mut self, schema: &Schema) -> Result<Value, Error> {
+        self.value_at_depth(schema, 0)
+    }
+
+    fn value_at_depth(&mut self, schema: &Schema, depth: usize) -> Result<Value, Error> {
+        let value = match *schema {
+            Schema::Null => Value::Null,
+            Schema::Boolean => Value::Boolean(self.rng.gen()),
+            Schema::Int => Value::Int(self.rng.gen()),
+            Schema::Long => Value::Long(self.rng.gen()),
+            Schema::Float => Value::Float(self.rng.gen_range(-1e6, 1e6)),
+            Schema::Double => Value::Double(self.rng.gen_range(-1e12, 1e12)),
+            Schema::Bytes => {
+                let len = self.len(self.options.max_string_len, 0);
+                Value::Bytes(self.bytes(len))
+            },
+            Schema::String => Value::String(self.string()),
+            Schema::Fixed { size, .. } => Value::Fixed(size, self.bytes(size)),
+            Schema::Enum { ref symbols, .. } if symbols.is_empty() => {
+                return Err(err_msg(format!(
+                    "enum `{}` has no symbols to choose from",
+                    schema_name(schema)
+                )))
+            },
+            Schema::Enum { ref symbols, .. } => {
+                let index = self.rng.gen_range(0, symbols.len());
+                Value::Enum(index as i32, symbols[index].clone())
+            },
+            Schema::Union(ref inner) => {
+                if depth >= self.options.max_depth
+                    || self.rng.gen::<f64>() < self.options.null_probability
+                {
+                    Value::Union(None)
+                } else {
+                    Value::Union(Some(Box::new(self.value_at_depth(inner, depth + 1)?)))
+                }
+            },
+            Schema::Array(ref inner) => {
+                let len = self.len(self.options.max_array_len, depth);
+                Value::Array(
+                    (0..len)
+                        .map(|_| self.value_at_depth(inner, depth + 1))
+                        .collect::<Result<_, _>>()?,
+                )
+            },
+            Schema::Map(ref inner) => {
+                let len = self.len(self.options.max_map_len, depth);
+                let mut items = HashMap::with_capacity(len);
+                for _ in 0..len {
+                    let key = self.string();
+                    let value = self.value_at_depth(inner, depth + 1)?;
+                    items.insert(key, value);
+                }
+                Value::Map(items)
+            },
+            Schema::Record { ref fields, .. } => Value::Record(
+                fields
+                    .iter()
+                    .map(|field| {
+                        let value = self.value_at_depth(&field.schema, depth + 1)?;
+                        Ok((field.name.clone(), value))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+        };
+        Ok(value)
+    }
+}
+
+/// Default options for the generation of random avro values.
+#[no_mangle]
+pub extern "C" fn avro_random_options_default() -> AvroRandomOptions {
+    AvroRandomOptions::default()
+}
+
+unsafe fn random_values(
+    seed: u64,
+    options: *const AvroRandomOptions,
+) -> Result<RandomValues, Error> {
+    let options = if options.is_null() {
+        AvroRandomOptions::default()
+    } else {
+        (*options).clone()
+    };
+    RandomValues::new(seed, options)
+}
+
+ffi_fn! {
+    /// Create a random avro value of a schema. The same seed always gives the same value.
+    ///
+    /// Default options are used if `options` is null.
+    unsafe fn avro_value_random(
+        schema: *const AvroSchema,
+        seed: u64,
+        options: *const AvroRandomOptions
+    ) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        let mut random = random_values(seed, options)?;
+        Ok(ffi_avro_value!(random.value(schema)?))
+    }
+}
+
+ffi_fn! {
+    /// Append `n` random avro values of the writer schema to an avro writer. The same seed
+    /// always gives the same values.
+    ///
+    /// Default options are used if `options` is null.
+    unsafe fn avro_writer_append_random(
+        writer: *mut AvroWriter,
+        seed: u64,
+        options: *const AvroRandomOptions,
+        n: usize
+    ) -> Result<usize> {
+        let writer = &mut (*(writer as *mut WriterHandle)).writer;
+        let mut random = random_values(seed, options)?;
+        let mut num_bytes = 0;
+        for _ in 0..n {
+            let value = random.value(writer.schema())?;
+            num_bytes += writer.append_value_ref(&value)?;
+        }
+        Ok(num_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_values() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "id", "type": "long"},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}},
+            {"name": "hash", "type": {"type": "fixed", "name": "md5", "size": 16}},
+            {"name": "tags", "type": {"type": "map", "values": {"type": "array", "items": "float"}}}
+        ]}
+        "#).unwrap();
+
+        let values = |seed| {
+            let mut random = RandomValues::new(seed, AvroRandomOptions::default()).unwrap();
+            (0..10).map(|_| random.value(&schema).unwrap()).collect::<Vec<_>>()
+        };
+
+        let first = values(42);
+        assert!(first.iter().all(|value| value.validate(&schema)));
+        assert_eq!(first, values(42));
+        assert_ne!(first, values(43));
+    }
+
+    #[test]
+    fn random_values_depth() {
+        let schema = Schema::parse_str(r#"{"type": "array", "items": ["null", "long"]}"#).unwrap();
+        let options = AvroRandomOptions {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let mut random = RandomValues::new(0, options).unwrap();
+        for _ in 0..10 {
+            match random.value(&schema).unwrap() {
+                Value::Array(items) => {
+                    assert!(items.iter().all(|item| *item == Value::Union(None)))
+                },
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn random_empty_enum() {
+        let schema = Schema::parse_str(r#"{"type": "enum", "name": "Empty", "symbols": []}"#);
+        let mut random = RandomValues::new(0, AvroRandomOptions::default()).unwrap();
+        let err = random.value(&schema.unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "enum `Empty` has no symbols to choose from");
+    }
+}
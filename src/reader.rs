@@ -1,15 +1,59 @@
 use avro_rs::from_avro_datum;
 use avro_rs::schema::Schema;
+use avro_rs::types::Value;
 use avro_rs::Reader;
-use avro_utils::pickle_value_from_avro;
+use avro_utils::{pickle_from_avro, pickle_list_from_avro};
 use core::AvroByteArray;
+use failure::Error;
+use options::{conversion_options, AvroConversionOptions, ConversionOptions};
 use schema::AvroSchema;
-use serde_pickle::ser::value_to_vec;
 use std::ptr;
 use types::AvroValue;
 
 pub struct AvroReader;
 
+/// An avro reader, along with the options to convert the values read from it to pickle.
+pub struct ReaderHandle<'a> {
+    pub reader: Reader<'a, &'a [u8]>,
+    pub options: ConversionOptions,
+    /// Error met while reading a batch of values, reported by the next read so that the values
+    /// read before it are not lost.
+    pub error: Option<Error>,
+}
+
+impl<'a> ReaderHandle<'a> {
+    fn boxed(reader: Reader<'a, &'a [u8]>, options: ConversionOptions) -> *mut AvroReader {
+        let error = None;
+        Box::into_raw(Box::new(ReaderHandle { reader, options, error })) as *mut AvroReader
+    }
+
+    /// Read the next value, or the error met by the previous batch if any.
+    pub fn next_value(&mut self) -> Option<Result<Value, Error>> {
+        match self.error.take() {
+            Some(err) => Some(Err(err)),
+            None => self.reader.next(),
+        }
+    }
+
+    /// Read up to `max_records` values. Reading stops at the first error, which is returned if
+    /// no value was read before it, and reported by the next read otherwise.
+    pub fn read_values(&mut self, max_records: usize) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::new();
+        while values.len() < max_records {
+            match self.next_value() {
+                None => break,
+                Some(Ok(value)) => values.push(value),
+                Some(Err(err)) if values.is_empty() => return Err(err),
+                Some(Err(err)) => {
+                    self.error = Some(err);
+                    break
+                },
+            }
+        }
+        Ok(values)
+    }
+}
+
 ffi_fn! {
     /// Create an avro writer given an avro schema, an avro byte array used as buffer and an avro codec.
     unsafe fn avro_reader_new(
@@ -20,20 +64,35 @@ ffi_fn! {
             None => Reader::new((&*buffer).as_slice())?,
             Some(s) => Reader::with_schema(&*(s as *const Schema), (&*buffer).as_slice())?,
         };
-        Ok(Box::into_raw(Box::new(reader)) as *mut AvroReader)
+        Ok(ReaderHandle::boxed(reader, ConversionOptions::default()))
+    }
+}
+
+ffi_fn! {
+    /// Create an avro reader given an avro byte array used as buffer, an avro schema (which may
+    /// be null to use the schema of the data) and the options to convert the values read with
+    /// `avro_reader_read_next`. Default options are used if `options` is null.
+    unsafe fn avro_reader_new_with_options(
+        buffer: *const AvroByteArray,
+        schema: *const AvroSchema,
+        options: *const AvroConversionOptions
+    ) -> Result<*mut AvroReader> {
+        let reader = if schema.is_null() {
+            Reader::new((&*buffer).as_slice())?
+        } else {
+            Reader::with_schema(&*(schema as *const Schema), (&*buffer).as_slice())?
+        };
+        Ok(ReaderHandle::boxed(reader, conversion_options(options)?))
     }
 }
 
 ffi_fn! {
     /// Read the next chunk of data out of an avro reader.
     unsafe fn avro_reader_read_next(reader: *mut AvroReader) -> Result<AvroByteArray> {
-        let reader = &mut *(reader as *mut Reader<&[u8]>);
-        match reader.next() {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        match handle.next_value() {
             None => Ok(AvroByteArray::default()),
-            Some(v) => Ok(AvroByteArray::from_vec_u8(value_to_vec(
-                &pickle_value_from_avro(v?),
-                false
-            )?)),
+            Some(v) => Ok(AvroByteArray::from_vec_u8(pickle_from_avro(&v?, &handle.options)?)),
         }
     }
 }
@@ -41,19 +100,58 @@ ffi_fn! {
 ffi_fn! {
     /// Read the next chunk of data out of an avro reader.
     unsafe fn avro_reader_read_next2(reader: *mut AvroReader) -> Result<*mut AvroValue> {
-        let reader = &mut *(reader as *mut Reader<&[u8]>);
-        match reader.next() {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        match handle.next_value() {
             None => Ok(ptr::null_mut()),
             Some(v) => Ok(Box::into_raw(Box::new(v?)) as *mut AvroValue),
         }
     }
 }
 
+ffi_fn! {
+    /// Read up to `max_records` values out of an avro reader, as a pickled list. The list is
+    /// empty once all the values are read.
+    ///
+    /// Reading stops at the first value which cannot be read: the values read before it are
+    /// returned, and the error is reported by the next read.
+    unsafe fn avro_reader_read_batch(
+        reader: *mut AvroReader,
+        max_records: usize
+    ) -> Result<AvroByteArray> {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        let values = handle.read_values(max_records)?;
+        Ok(AvroByteArray::from_vec_u8(pickle_list_from_avro(&values, &handle.options)?))
+    }
+}
+
+ffi_fn! {
+    /// Read all the remaining values out of an avro reader, as a pickled list. As with
+    /// `avro_reader_read_batch`, an error is reported by the next read if values were read
+    /// before it.
+    unsafe fn avro_reader_read_all(reader: *mut AvroReader) -> Result<AvroByteArray> {
+        let handle = &mut *(reader as *mut ReaderHandle);
+        let values = handle.read_values(usize::MAX)?;
+        Ok(AvroByteArray::from_vec_u8(pickle_list_from_avro(&values, &handle.options)?))
+    }
+}
+
+ffi_fn! {
+    /// Read up to `max_records` values out of an avro reader, as an array value. The array is
+    /// empty once all the values are read. Errors are reported as by `avro_reader_read_batch`.
+    unsafe fn avro_reader_read_batch2(
+        reader: *mut AvroReader,
+        max_records: usize
+    ) -> Result<*mut AvroValue> {
+        let values = (*(reader as *mut ReaderHandle)).read_values(max_records)?;
+        Ok(ffi_avro_value!(Value::Array(values)))
+    }
+}
+
 ffi_fn! {
     /// Free an avro reader. Does NOT free the buffer the reader reads from.
     unsafe fn avro_reader_free(reader: *mut AvroReader) {
         if !reader.is_null() {
-            Box::from_raw(reader as *mut Reader<&[u8]>);
+            Box::from_raw(reader as *mut ReaderHandle);
         }
     }
 }
@@ -64,9 +162,23 @@ ffi_fn! {
         let mut reader = (&*buffer).as_slice();
         let schema = &*(schema as *const Schema);
         let value = from_avro_datum(schema, &mut reader, None)?;
-        Ok(AvroByteArray::from_vec_u8(value_to_vec(
-            &pickle_value_from_avro(value),
-            false
-        )?))
+        let options = ConversionOptions::default();
+        Ok(AvroByteArray::from_vec_u8(pickle_from_avro(&value, &options)?))
+    }
+}
+
+ffi_fn! {
+    /// Read avro serialized data into a pickled value, converted with the given options.
+    /// Default options are used if `options` is null.
+    unsafe fn avro_from_avro_datum_with_options(
+        buffer: *const AvroByteArray,
+        schema: *const AvroSchema,
+        options: *const AvroConversionOptions
+    ) -> Result<AvroByteArray> {
+        let mut reader = (&*buffer).as_slice();
+        let schema = &*(schema as *const Schema);
+        let options = conversion_options(options)?;
+        let value = from_avro_datum(schema, &mut reader, None)?;
+        Ok(AvroByteArray::from_vec_u8(pickle_from_avro(&value, &options)?))
     }
 }
@@ -1,5 +1,6 @@
 use avro_rs::schema::Schema;
 use core::AvroStr;
+use failure::err_msg;
 
 pub struct AvroSchema;
 
@@ -11,6 +12,42 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Get the schema of a field of a record schema. The schema is borrowed from the record
+    /// schema.
+    unsafe fn avro_schema_record_field_get(
+        schema: *const AvroSchema,
+        field: *const AvroStr
+    ) -> Result<*const AvroSchema> {
+        let field = (&*field).as_str();
+        match *(schema as *const Schema) {
+            Schema::Record { ref fields, ref lookup, .. } => lookup
+                .get(field)
+                .map(|&position| &fields[position].schema as *const Schema as *const AvroSchema)
+                .ok_or_else(|| err_msg(format!("field `{}` is not in record schema", field))),
+            _ => Err(err_msg("schema is not a record")),
+        }
+    }
+}
+
+ffi_fn! {
+    /// Get the position of a field in a record schema. Records read or created with the schema
+    /// have their fields in the same order, to be accessed with `avro_value_record_get_by_index`.
+    unsafe fn avro_schema_record_field_index(
+        schema: *const AvroSchema,
+        field: *const AvroStr
+    ) -> Result<usize> {
+        let field = (&*field).as_str();
+        match *(schema as *const Schema) {
+            Schema::Record { ref lookup, .. } => lookup
+                .get(field)
+                .cloned()
+                .ok_or_else(|| err_msg(format!("field `{}` is not in record schema", field))),
+            _ => Err(err_msg("schema is not a record")),
+        }
+    }
+}
+
 #[no_mangle]
 /// Free an avro schema.
 pub unsafe extern "C" fn avro_schema_free(schema: *mut AvroSchema) {
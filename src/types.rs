@@ -1,15 +1,66 @@
-use avro_rs::schema::Schema;
+use avro_rs::schema::{RecordField, Schema};
 use avro_rs::types::ToAvro;
 use avro_rs::types::{Record, Value};
+use avro_utils::{
+    avro_record_from_defaults, avro_value_from_json_default, coerce_value, schema_matches_name,
+    schema_name,
+};
 use core::{AvroByteArray, AvroStr};
-use failure::err_msg;
+use failure::{err_msg, Error};
 use schema::AvroSchema;
 use std::collections::HashMap;
+use std::convert::identity;
 use std::os::raw::{c_double, c_float, c_int, c_long};
+use std::slice;
 
 pub struct AvroRecord;
 pub struct AvroValue;
 
+/// A `Record` under construction, along with the fields of the schema it was created from.
+struct RecordBuilder<'a> {
+    fields: &'a [RecordField],
+    lookup: &'a HashMap<String, usize>,
+    record: Record,
+}
+
+impl<'a> RecordBuilder<'a> {
+    fn new(schema: &'a Schema) -> Result<RecordBuilder<'a>, Error> {
+        match *schema {
+            Schema::Record {
+                ref fields,
+                ref lookup,
+                ..
+            } => Ok(RecordBuilder {
+                fields,
+                lookup,
+                record: Record::new(schema).ok_or_else(|| err_msg("record creation failed"))?,
+            }),
+            _ => Err(err_msg("schema is not a record")),
+        }
+    }
+
+    fn position(&self, field: &str) -> Result<usize, Error> {
+        self.lookup
+            .get(field)
+            .cloned()
+            .ok_or_else(|| err_msg(format!("field `{}` is not in record schema", field)))
+    }
+
+    fn put(&mut self, field: &str, value: Value) -> Result<(), Error> {
+        let position = self.position(field)?;
+        let value = coerce_value(&self.fields[position].schema, value).map_err(|err| {
+            err_msg(format!("value does not match schema of field `{}`: {}", field, err))
+        })?;
+        self.record.fields[position].1 = value;
+        Ok(())
+    }
+
+    fn get(&self, field: &str) -> Result<&Value, Error> {
+        let position = self.position(field)?;
+        Ok(&self.record.fields[position].1)
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn avro_value_free(v: *mut AvroValue) {
     if !v.is_null() {
@@ -20,7 +71,7 @@ pub unsafe extern "C" fn avro_value_free(v: *mut AvroValue) {
 #[no_mangle]
 pub unsafe extern "C" fn avro_record_free(r: *mut AvroRecord) {
     if !r.is_null() {
-        Box::from_raw(r as *mut Record);
+        Box::from_raw(r as *mut RecordBuilder);
     }
 }
 
@@ -146,6 +197,30 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Create an enum value from one of the symbols of an enum schema.
+    unsafe fn avro_value_enum_symbol_new(
+        schema: *const AvroSchema,
+        symbol: AvroStr
+    ) -> Result<*mut AvroValue> {
+        let symbol = symbol.into_string();
+        match *(schema as *const Schema) {
+            Schema::Enum { ref name, ref symbols, .. } => {
+                match symbols.iter().position(|item| *item == symbol) {
+                    Some(index) => Ok(ffi_avro_value!(Value::Enum(index as i32, symbol))),
+                    None => Err(err_msg(format!(
+                        "symbol `{}` is not in enum `{}` (symbols: {:?})",
+                        symbol,
+                        name.fullname(None),
+                        symbols
+                    ))),
+                }
+            },
+            _ => Err(err_msg("schema is not an enum")),
+        }
+    }
+}
+
 ffi_fn! {
     unsafe fn avro_value_enum_get(value: *const AvroValue) -> Result<AvroStr> {
         let value = &*(value as * const Value);
@@ -160,6 +235,29 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Create a fixed value for a fixed schema, checking that the size of the bytes matches.
+    unsafe fn avro_value_fixed_schema_new(
+        schema: *const AvroSchema,
+        b: AvroByteArray
+    ) -> Result<*mut AvroValue> {
+        let bytes = b.into_vec_u8();
+        match *(schema as *const Schema) {
+            Schema::Fixed { ref name, size } => if bytes.len() == size {
+                Ok(ffi_avro_value!(Value::Fixed(size, bytes)))
+            } else {
+                Err(err_msg(format!(
+                    "fixed `{}` expects {} bytes, got {}",
+                    name.fullname(None),
+                    size,
+                    bytes.len()
+                )))
+            },
+            _ => Err(err_msg("schema is not a fixed")),
+        }
+    }
+}
+
 ffi_fn! {
     unsafe fn avro_value_fixed_get(value: *const AvroValue) -> Result<AvroByteArray> {
         let value = &*(value as *const Value);
@@ -178,6 +276,125 @@ ffi_fn! {
     }
 }
 
+/// Build a value of a union schema. avro-rs only supports unions of `null` and one other type,
+/// `["null", T]`: no value or a null value is the `null` branch, any other value is checked
+/// against `T`.
+fn union_value(schema: &Schema, value: Option<Value>) -> Result<Value, Error> {
+    let inner = match *schema {
+        Schema::Union(ref inner) => inner,
+        _ => return Err(err_msg("schema is not a union")),
+    };
+
+    match value {
+        None | Some(Value::Null) | Some(Value::Union(None)) => Ok(Value::Union(None)),
+        Some(value) => coerce_value(inner, value)
+            .map(|value| Value::Union(Some(Box::new(value))))
+            .map_err(|err| {
+                err_msg(format!(
+                    "value does not match union branch `{}`: {}",
+                    schema_name(inner),
+                    err
+                ))
+            }),
+    }
+}
+
+ffi_fn! {
+    /// Create a value of a union schema, checking it against the schema. Only unions of `null`
+    /// and one other type, `["null", T]`, are supported by avro-rs.
+    ///
+    /// A null pointer or a null value gives the `null` branch, any other value is checked
+    /// against `T`. The value is consumed in any case.
+    unsafe fn avro_value_union_schema_new(
+        schema: *const AvroSchema,
+        value: *mut AvroValue
+    ) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        let value = if value.is_null() { None } else { Some(*Box::from_raw(value as *mut Value)) };
+        Ok(ffi_avro_value!(union_value(schema, value)?))
+    }
+}
+
+/// Build a value for the branch at a given index of a `["null", T]` union schema: 0 for `null`,
+/// 1 for `T`.
+fn union_branch_value(
+    schema: &Schema,
+    branch: usize,
+    value: Option<Value>,
+) -> Result<Value, Error> {
+    let inner = match *schema {
+        Schema::Union(ref inner) => inner,
+        _ => return Err(err_msg("schema is not a union")),
+    };
+
+    match (branch, value) {
+        (0, None) | (0, Some(Value::Null)) => Ok(Value::Union(None)),
+        (0, Some(_)) => Err(err_msg("the null branch of a union does not take a value")),
+        (1, None) => Err(err_msg(format!(
+            "union branch `{}` needs a value",
+            schema_name(inner)
+        ))),
+        (1, Some(value)) => union_value(schema, Some(value)),
+        (branch, _) => Err(err_msg(format!(
+            "union branch {} out of range, union [\"null\", \"{}\"] has 2 branches",
+            branch,
+            schema_name(inner)
+        ))),
+    }
+}
+
+ffi_fn! {
+    /// Create a value for the branch at a given index of a union schema: 0 for `null` and 1 for
+    /// `T` in a `["null", T]` union, the only unions supported by avro-rs. Any other index is an
+    /// error.
+    ///
+    /// The value is checked against the schema of the branch, and consumed in any case. A null
+    /// pointer or a null value can be given for the `null` branch.
+    unsafe fn avro_value_union_branch_new(
+        schema: *const AvroSchema,
+        branch: usize,
+        value: *mut AvroValue
+    ) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        let value = if value.is_null() { None } else { Some(*Box::from_raw(value as *mut Value)) };
+        Ok(ffi_avro_value!(union_branch_value(schema, branch, value)?))
+    }
+}
+
+ffi_fn! {
+    /// Create a value of a union schema for the branch with a given type name: `null`, or the
+    /// name of `T` (e.g. `long` or the name of a record) for a `["null", T]` union, the only
+    /// unions supported by avro-rs.
+    ///
+    /// The value is checked against the schema of the branch, and consumed in any case. A null
+    /// pointer or a null value can be given for the `null` branch.
+    unsafe fn avro_value_union_named_new(
+        schema: *const AvroSchema,
+        name: *const AvroStr,
+        value: *mut AvroValue
+    ) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        let name = (&*name).as_str();
+        let value = if value.is_null() { None } else { Some(*Box::from_raw(value as *mut Value)) };
+        let inner = match *schema {
+            Schema::Union(ref inner) => inner,
+            _ => return Err(err_msg("schema is not a union")),
+        };
+        let branch = if name == "null" {
+            0
+        } else if schema_matches_name(inner, name) {
+            1
+        } else {
+            return Err(err_msg(format!(
+                "no branch `{}` in union [\"null\", \"{}\"]",
+                name,
+                schema_name(inner)
+            )))
+        };
+        Ok(ffi_avro_value!(union_branch_value(schema, branch, value)?))
+    }
+}
+
 ffi_fn! {
     unsafe fn avro_value_array_new(capacity: usize) -> Result<*mut AvroValue> {
         Ok(ffi_avro_value!(Value::Array(Vec::with_capacity(capacity))))
@@ -200,6 +417,103 @@ ffi_fn! {
     }
 }
 
+ffi_fn! {
+    /// Number of items in an array.
+    unsafe fn avro_value_array_len(array: *const AvroValue) -> Result<usize> {
+        match *(array as *const Value) {
+            Value::Array(ref items) => Ok(items.len()),
+            _ => Err(err_msg("Value is not an array")),
+        }
+    }
+}
+
+/// Define the functions copying an array of primitive values from and to a C buffer, items of
+/// the buffer being converted from and to the values of the array by `$from_c` and `$to_c`, and
+/// `$note` being appended to the docs.
+macro_rules! array_buffer_fns {
+    ($from:ident, $to:ident, $t:ty, $variant:ident, $kind:expr) => {
+        array_buffer_fns!($from, $to, $t, $variant, $kind, identity, identity);
+    };
+    (
+        $from:ident,
+        $to:ident,
+        $t:ty,
+        $variant:ident,
+        $kind:expr,
+        $from_c:path,
+        $to_c:path
+        $(, $note:expr)*
+    ) => {
+        ffi_fn! {
+            #[doc = "Create an array of "]
+            #[doc = $kind]
+            #[doc = " values, copied from a buffer of `len` items."]
+            $(#[doc = ""] #[doc = $note])*
+            unsafe fn $from(data: *const $t, len: usize) -> Result<*mut AvroValue> {
+                let data = if len == 0 { &[] } else { slice::from_raw_parts(data, len) };
+                let items = data.iter().map(|&x| Value::$variant($from_c(x))).collect();
+                Ok(ffi_avro_value!(Value::Array(items)))
+            }
+        }
+
+        ffi_fn! {
+            #[doc = "Copy an array of "]
+            #[doc = $kind]
+            #[doc = " values to a buffer of `len` items, returning the number of items copied."]
+            #[doc = ""]
+            #[doc = "Fails if the array is longer than the buffer or if an item is not a "]
+            #[doc = $kind]
+            #[doc = "."]
+            $(#[doc = ""] #[doc = $note])*
+            unsafe fn $to(array: *const AvroValue, out: *mut $t, len: usize) -> Result<usize> {
+                let items = match *(array as *const Value) {
+                    Value::Array(ref items) => items,
+                    _ => return Err(err_msg("Value is not an array")),
+                };
+                if items.len() > len {
+                    return Err(err_msg(format!(
+                        "array of {} items does not fit in a buffer of {}",
+                        items.len(),
+                        len
+                    )))
+                }
+                for (i, item) in items.iter().enumerate() {
+                    match *item {
+                        Value::$variant(x) => *out.add(i) = $to_c(x),
+                        _ => return Err(err_msg(concat!("array item is not a ", $kind))),
+                    }
+                }
+                Ok(items.len())
+            }
+        }
+    };
+}
+
+fn bool_from_c(b: u8) -> bool {
+    b != 0
+}
+
+array_buffer_fns!(
+    avro_value_array_from_bools,
+    avro_value_array_to_bools,
+    u8,
+    Boolean,
+    "boolean",
+    bool_from_c,
+    u8::from,
+    "Booleans are bytes: any non-zero byte is true, and true is copied out as 1."
+);
+array_buffer_fns!(avro_value_array_from_ints, avro_value_array_to_ints, i32, Int, "int");
+array_buffer_fns!(avro_value_array_from_longs, avro_value_array_to_longs, i64, Long, "long");
+array_buffer_fns!(avro_value_array_from_floats, avro_value_array_to_floats, f32, Float, "float");
+array_buffer_fns!(
+    avro_value_array_from_doubles,
+    avro_value_array_to_doubles,
+    f64,
+    Double,
+    "double"
+);
+
 ffi_fn! {
     unsafe fn avro_value_map_new(capacity: usize) -> Result<*mut AvroValue> {
         Ok(ffi_avro_value!(Value::Map(HashMap::with_capacity(capacity))))
@@ -224,31 +538,60 @@ ffi_fn! {
 }
 
 ffi_fn! {
+    /// Create a complete record value for a record schema, out of the defaults of its fields.
+    ///
+    /// Nullable fields without a default are set to null. Fails, listing them, if some fields
+    /// have no default and are not nullable.
+    unsafe fn avro_value_default_for_schema(schema: *const AvroSchema) -> Result<*mut AvroValue> {
+        let schema = &*(schema as *const Schema);
+        Ok(ffi_avro_value!(avro_record_from_defaults(schema)?))
+    }
+}
+
+ffi_fn! {
+    /// Create an empty record for a record schema.
+    ///
+    /// The schema must outlive the record.
     unsafe fn avro_record_new(schema: *const AvroSchema) -> Result<*mut AvroRecord> {
         let schema = &*(schema as *const Schema);
-        let record = Record::new(schema).ok_or_else(|| err_msg("record creation failed"))?;
-        Ok(Box::into_raw(Box::new(record)) as *mut AvroRecord)
+        let builder = RecordBuilder::new(schema)?;
+        Ok(Box::into_raw(Box::new(builder)) as *mut AvroRecord)
     }
 }
 
 ffi_fn! {
+    /// Set a field of a record.
+    ///
+    /// Fails if the field is not in the record schema or if the value does not match the schema
+    /// of the field. The value is consumed in any case.
     unsafe fn avro_record_put(
         record: *mut AvroRecord,
         field: *const AvroStr,
         value: *mut AvroValue
     ) -> Result<()> {
-        let record = &mut *(record as *mut Record);
+        let record = &mut *(record as *mut RecordBuilder);
         let field = (&*field).as_str();
         let value = *(Box::from_raw(value as *mut Value));
-        record.put(&field, value); // TODO: CAN'T FAIL???
-        Ok(())
+        record.put(field, value)
+    }
+}
+
+ffi_fn! {
+    /// Get a field of a record under construction. The value is borrowed from the record.
+    unsafe fn avro_record_get(
+        record: *const AvroRecord,
+        field: *const AvroStr
+    ) -> Result<*const AvroValue> {
+        let record = &*(record as *const RecordBuilder);
+        let field = (&*field).as_str();
+        Ok(record.get(field)? as *const Value as *const AvroValue)
     }
 }
 
 ffi_fn! {
     unsafe fn avro_record_to_value(record: *mut AvroRecord) -> Result<*mut AvroValue> {
-        let record = *(Box::from_raw(record as *mut Record));
-        Ok(ffi_avro_value!(record.avro()))
+        let builder = *(Box::from_raw(record as *mut RecordBuilder));
+        Ok(ffi_avro_value!(builder.record.avro()))
     }
 }
 
@@ -260,13 +603,137 @@ ffi_fn! {
         let field = (&*field).as_str();
         let record = &*(record as *const Value);
         if let Value::Record(ref fields) = *record {
-            if let Some(&(_, ref v)) = fields.iter().find(|&&(ref n, _)| n == field) {
+            if let Some((_, v)) = fields.iter().find(|(n, _)| n == field) {
                 Ok(v as *const Value as *const AvroValue)
             } else {
-                Err(err_msg("Field not in record")) // TODO: WHAT ABOUT NULLABLE VALUE? AND OTHER COMPATIBLE SCHEMAS?
+                Err(err_msg("Field not in record")) // see avro_value_record_get_or_default for defaults
             }
         } else {
             Err(err_msg("Value is not a record"))
         }
     }
 }
+
+/// Field of a record value at a given position.
+unsafe fn record_field<'a>(
+    record: *const AvroValue,
+    index: usize,
+) -> Result<&'a (String, Value), Error> {
+    match *(record as *const Value) {
+        Value::Record(ref fields) => fields.get(index).ok_or_else(|| {
+            err_msg(format!(
+                "field index {} out of bounds for a record of {} fields",
+                index,
+                fields.len()
+            ))
+        }),
+        _ => Err(err_msg("Value is not a record")),
+    }
+}
+
+ffi_fn! {
+    /// Number of fields of a record.
+    unsafe fn avro_value_record_field_count(record: *const AvroValue) -> Result<usize> {
+        match *(record as *const Value) {
+            Value::Record(ref fields) => Ok(fields.len()),
+            _ => Err(err_msg("Value is not a record")),
+        }
+    }
+}
+
+ffi_fn! {
+    /// Get the name of the field of a record at a given position. The name is borrowed from the
+    /// record.
+    unsafe fn avro_value_record_field_name(
+        record: *const AvroValue,
+        index: usize
+    ) -> Result<AvroStr> {
+        Ok(AvroStr::new(&record_field(record, index)?.0))
+    }
+}
+
+ffi_fn! {
+    /// Get the field of a record at a given position, as found with
+    /// `avro_schema_record_field_index`. The value is borrowed from the record.
+    unsafe fn avro_value_record_get_by_index(
+        record: *const AvroValue,
+        index: usize
+    ) -> Result<*const AvroValue> {
+        Ok(&record_field(record, index)?.1 as *const Value as *const AvroValue)
+    }
+}
+
+/// Null value standing for null unions, when unwrapping them.
+static NULL: Value = Value::Null;
+
+/// Step through non-null unions to their value, and give a null value for null unions.
+fn unwrap_union(mut value: &Value) -> &Value {
+    loop {
+        match *value {
+            Value::Union(Some(ref inner)) => value = inner,
+            Value::Union(None) => return &NULL,
+            _ => return value,
+        }
+    }
+}
+
+fn find_field<'a>(record: &'a Value, field: &str) -> Result<Option<&'a Value>, Error> {
+    match *record {
+        Value::Record(ref fields) => Ok(fields
+            .iter()
+            .find(|&(name, _)| name == field)
+            .map(|(_, value)| value)),
+        _ => Err(err_msg("Value is not a record")),
+    }
+}
+
+ffi_fn! {
+    /// Get a field of a record, stepping through unions: a null union gives a null value, and
+    /// a non-null union the value it holds. The value is borrowed from the record.
+    unsafe fn avro_value_record_get_unwrapped(
+        record: *const AvroValue,
+        field: *const AvroStr
+    ) -> Result<*const AvroValue> {
+        let field = (&*field).as_str();
+        let value = find_field(&*(record as *const Value), field)?
+            .ok_or_else(|| err_msg(format!("field `{}` is not in record", field)))?;
+        Ok(unwrap_union(value) as *const Value as *const AvroValue)
+    }
+}
+
+ffi_fn! {
+    /// Get a field of a record, stepping through unions like `avro_value_record_get_unwrapped`.
+    ///
+    /// Fields absent from the record fall back to their default in the reader schema of the
+    /// record, or to null for nullable fields without a default. The value is a copy, to be
+    /// freed with `avro_value_free`.
+    unsafe fn avro_value_record_get_or_default(
+        record: *const AvroValue,
+        field: *const AvroStr,
+        schema: *const AvroSchema
+    ) -> Result<*mut AvroValue> {
+        let field = (&*field).as_str();
+        if let Some(value) = find_field(&*(record as *const Value), field)? {
+            return Ok(ffi_avro_value!(unwrap_union(value).clone()))
+        }
+
+        let record_field = match *(schema as *const Schema) {
+            Schema::Record { ref fields, ref lookup, .. } => lookup
+                .get(field)
+                .map(|&position| &fields[position])
+                .ok_or_else(|| err_msg(format!("field `{}` is not in record schema", field)))?,
+            _ => return Err(err_msg("schema is not a record")),
+        };
+        let value = match (record_field.default.as_ref(), &record_field.schema) {
+            (Some(default), schema) => avro_value_from_json_default(schema, default)?,
+            (None, &Schema::Union(_)) => Value::Null,
+            (None, _) => {
+                return Err(err_msg(format!(
+                    "field `{}` is not in record and has no default",
+                    field
+                )))
+            },
+        };
+        Ok(ffi_avro_value!(unwrap_union(&value).clone()))
+    }
+}
@@ -0,0 +1,395 @@
+//! Event-driven traversal of avro values, firing C callbacks instead of building values.
+use std::os::raw::{c_int, c_void};
+use std::str;
+
+use avro_rs::schema::Schema;
+use avro_rs::types::Value;
+use binary::{
+    read_block_len, read_boolean, read_bytes, read_double, read_fixed, read_float, read_int,
+    read_long, read_union_branch,
+};
+use core::{AvroByteArray, AvroStr};
+use failure::{err_msg, Error};
+use schema::AvroSchema;
+use types::AvroValue;
+
+/// Callbacks fired while walking an avro value.
+///
+/// Every callback is optional and receives `user_data` as its first argument. Strings and
+/// bytes are borrowed and only valid during the callback. Returning a non-zero value from a
+/// callback stops the walk.
+#[repr(C)]
+pub struct AvroVisitor {
+    pub user_data: *mut c_void,
+    pub on_null: Option<extern "C" fn(*mut c_void) -> c_int>,
+    pub on_boolean: Option<extern "C" fn(*mut c_void, bool) -> c_int>,
+    pub on_int: Option<extern "C" fn(*mut c_void, i32) -> c_int>,
+    pub on_long: Option<extern "C" fn(*mut c_void, i64) -> c_int>,
+    pub on_float: Option<extern "C" fn(*mut c_void, f32) -> c_int>,
+    pub on_double: Option<extern "C" fn(*mut c_void, f64) -> c_int>,
+    pub on_bytes: Option<extern "C" fn(*mut c_void, AvroByteArray) -> c_int>,
+    pub on_string: Option<extern "C" fn(*mut c_void, AvroStr) -> c_int>,
+    pub on_fixed: Option<extern "C" fn(*mut c_void, AvroByteArray) -> c_int>,
+    /// Called with the index and the symbol of the enum.
+    pub on_enum: Option<extern "C" fn(*mut c_void, c_int, AvroStr) -> c_int>,
+    /// Called with the branch index of a union, before the branch value.
+    pub on_union: Option<extern "C" fn(*mut c_void, usize) -> c_int>,
+    pub on_array_start: Option<extern "C" fn(*mut c_void) -> c_int>,
+    pub on_array_end: Option<extern "C" fn(*mut c_void) -> c_int>,
+    pub on_map_start: Option<extern "C" fn(*mut c_void) -> c_int>,
+    /// Called with the key of a map entry, before its value.
+    pub on_map_key: Option<extern "C" fn(*mut c_void, AvroStr) -> c_int>,
+    pub on_map_end: Option<extern "C" fn(*mut c_void) -> c_int>,
+    pub on_record_start: Option<extern "C" fn(*mut c_void) -> c_int>,
+    /// Called with the name of a record field, before its value.
+    pub on_field: Option<extern "C" fn(*mut c_void, AvroStr) -> c_int>,
+    pub on_record_end: Option<extern "C" fn(*mut c_void) -> c_int>,
+}
+
+/// Fire a callback if it is set, returning early if it asks to stop the walk.
+macro_rules! fire {
+    ($visitor:expr, $callback:ident $(, $arg:expr)*) => {
+        if let Some(callback) = $visitor.$callback {
+            let code = callback($visitor.user_data $(, $arg)*);
+            if code != 0 {
+                return Ok(code)
+            }
+        }
+    };
+}
+
+/// Walk a nested value, returning early if the walk was stopped.
+macro_rules! walk {
+    ($e:expr) => {
+        match $e? {
+            0 => (),
+            code => return Ok(code),
+        }
+    };
+}
+
+/// Walk a value, firing the callbacks of the visitor.
+///
+/// Return 0 if the whole value was walked, or the non-zero code of the callback which stopped
+/// the walk.
+pub fn visit_value(visitor: &AvroVisitor, value: &Value) -> Result<c_int, Error> {
+    match *value {
+        Value::Null => fire!(visitor, on_null),
+        Value::Boolean(b) => fire!(visitor, on_boolean, b),
+        Value::Int(n) => fire!(visitor, on_int, n),
+        Value::Long(n) => fire!(visitor, on_long, n),
+        Value::Float(x) => fire!(visitor, on_float, x),
+        Value::Double(x) => fire!(visitor, on_double, x),
+        Value::Bytes(ref bytes) => fire!(visitor, on_bytes, AvroByteArray::new(bytes)),
+        Value::String(ref s) => fire!(visitor, on_string, AvroStr::new(s)),
+        Value::Fixed(_, ref bytes) => fire!(visitor, on_fixed, AvroByteArray::new(bytes)),
+        Value::Enum(index, ref symbol) => fire!(visitor, on_enum, index, AvroStr::new(symbol)),
+        Value::Union(None) => {
+            fire!(visitor, on_union, 0);
+            fire!(visitor, on_null);
+        },
+        Value::Union(Some(ref inner)) => {
+            fire!(visitor, on_union, 1);
+            walk!(visit_value(visitor, inner));
+        },
+        Value::Array(ref items) => {
+            fire!(visitor, on_array_start);
+            for item in items {
+                walk!(visit_value(visitor, item));
+            }
+            fire!(visitor, on_array_end);
+        },
+        Value::Map(ref items) => {
+            fire!(visitor, on_map_start);
+            for (key, item) in items {
+                fire!(visitor, on_map_key, AvroStr::new(key));
+                walk!(visit_value(visitor, item));
+            }
+            fire!(visitor, on_map_end);
+        },
+        Value::Record(ref fields) => {
+            fire!(visitor, on_record_start);
+            for (name, field) in fields {
+                fire!(visitor, on_field, AvroStr::new(name));
+                walk!(visit_value(visitor, field));
+            }
+            fire!(visitor, on_record_end);
+        },
+    }
+    Ok(0)
+}
+
+fn read_str<'a>(buf: &mut &'a [u8]) -> Result<&'a str, Error> {
+    str::from_utf8(read_bytes(buf)?).map_err(|_| err_msg("string is not valid utf-8"))
+}
+
+/// Walk a binary encoded value of a schema, firing the callbacks of the visitor without
+/// decoding the value.
+///
+/// Return 0 if the whole value was walked, or the non-zero code of the callback which stopped
+/// the walk. Blocks of arrays and maps with more items than bytes left are rejected before any
+/// of their items is walked, as the items of malformed data could otherwise take no bytes.
+pub fn visit_encoded(
+    visitor: &AvroVisitor,
+    schema: &Schema,
+    buf: &mut &[u8],
+) -> Result<c_int, Error> {
+    match *schema {
+        Schema::Null => fire!(visitor, on_null),
+        Schema::Boolean => fire!(visitor, on_boolean, read_boolean(buf)?),
+        Schema::Int => fire!(visitor, on_int, read_int(buf)?),
+        Schema::Long => fire!(visitor, on_long, read_long(buf)?),
+        Schema::Float => fire!(visitor, on_float, read_float(buf)?),
+        Schema::Double => fire!(visitor, on_double, read_double(buf)?),
+        Schema::Bytes => fire!(visitor, on_bytes, AvroByteArray::new(read_bytes(buf)?)),
+        Schema::String => fire!(visitor, on_string, AvroStr::new(read_str(buf)?)),
+        Schema::Fixed { size, .. } => {
+            fire!(
+                visitor,
+                on_fixed,
+                AvroByteArray::new(read_fixed(buf, size)?)
+            )
+        },
+        Schema::Enum { ref symbols, .. } => {
+            let index = read_int(buf)?;
+            let symbol = symbols
+                .get(index as usize)
+                .ok_or_else(|| err_msg("enum symbol index out of bounds"))?;
+            fire!(visitor, on_enum, index, AvroStr::new(symbol));
+        },
+        Schema::Union(ref inner) => {
+            let branch = read_union_branch(buf)?;
+            fire!(visitor, on_union, branch);
+            if branch == 0 {
+                fire!(visitor, on_null);
+            } else {
+                walk!(visit_encoded(visitor, inner, buf));
+            }
+        },
+        Schema::Array(ref inner) => {
+            fire!(visitor, on_array_start);
+            loop {
+                let len = read_block_len(buf)?;
+                if len == 0 {
+                    break
+                }
+                for _ in 0..len {
+                    walk!(visit_encoded(visitor, inner, buf));
+                }
+            }
+            fire!(visitor, on_array_end);
+        },
+        Schema::Map(ref inner) => {
+            fire!(visitor, on_map_start);
+            loop {
+                let len = read_block_len(buf)?;
+                if len == 0 {
+                    break
+                }
+                for _ in 0..len {
+                    fire!(visitor, on_map_key, AvroStr::new(read_str(buf)?));
+                    walk!(visit_encoded(visitor, inner, buf));
+                }
+            }
+            fire!(visitor, on_map_end);
+        },
+        Schema::Record { ref fields, .. } => {
+            fire!(visitor, on_record_start);
+            for field in fields {
+                fire!(visitor, on_field, AvroStr::new(&field.name));
+                walk!(visit_encoded(visitor, &field.schema, buf));
+            }
+            fire!(visitor, on_record_end);
+        },
+    }
+    Ok(0)
+}
+
+ffi_fn! {
+    /// Walk an avro value, firing the callbacks of a visitor.
+    ///
+    /// Return 0 if the whole value was walked, or the non-zero code of the callback which
+    /// stopped the walk.
+    unsafe fn avro_value_visit(
+        value: *const AvroValue,
+        visitor: *const AvroVisitor
+    ) -> Result<c_int> {
+        let value = &*(value as *const Value);
+        visit_value(&*visitor, value)
+    }
+}
+
+ffi_fn! {
+    /// Walk an avro serialized datum of a schema, firing the callbacks of a visitor. The datum
+    /// is not decoded into an avro value.
+    ///
+    /// Return 0 if the whole datum was walked, or the non-zero code of the callback which
+    /// stopped the walk.
+    ///
+    /// Blocks of arrays and maps with more items than bytes left in the datum are errors, so
+    /// that malformed data cannot make the walk fire callbacks for items it does not hold.
+    unsafe fn avro_datum_visit(
+        buffer: *const AvroByteArray,
+        schema: *const AvroSchema,
+        visitor: *const AvroVisitor
+    ) -> Result<c_int> {
+        let schema = &*(schema as *const Schema);
+        let mut buffer = (&*buffer).as_slice();
+        visit_encoded(&*visitor, schema, &mut buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::to_avro_datum;
+
+    fn events(user_data: *mut c_void) -> &'static mut Vec<String> {
+        unsafe { &mut *(user_data as *mut Vec<String>) }
+    }
+
+    extern "C" fn on_null(user_data: *mut c_void) -> c_int {
+        events(user_data).push("null".to_owned());
+        0
+    }
+
+    extern "C" fn on_long(user_data: *mut c_void, n: i64) -> c_int {
+        events(user_data).push(format!("long {}", n));
+        if n < 0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    extern "C" fn on_string(user_data: *mut c_void, s: AvroStr) -> c_int {
+        events(user_data).push(format!("string {}", s.as_str()));
+        0
+    }
+
+    extern "C" fn on_enum(user_data: *mut c_void, index: c_int, symbol: AvroStr) -> c_int {
+        events(user_data).push(format!("enum {} {}", index, symbol.as_str()));
+        0
+    }
+
+    extern "C" fn on_union(user_data: *mut c_void, branch: usize) -> c_int {
+        events(user_data).push(format!("union {}", branch));
+        0
+    }
+
+    extern "C" fn on_field(user_data: *mut c_void, name: AvroStr) -> c_int {
+        events(user_data).push(format!("field {}", name.as_str()));
+        0
+    }
+
+    extern "C" fn on_start(user_data: *mut c_void) -> c_int {
+        events(user_data).push("start".to_owned());
+        0
+    }
+
+    extern "C" fn on_end(user_data: *mut c_void) -> c_int {
+        events(user_data).push("end".to_owned());
+        0
+    }
+
+    fn visitor(events: &mut Vec<String>) -> AvroVisitor {
+        AvroVisitor {
+            user_data: events as *mut Vec<String> as *mut c_void,
+            on_null: Some(on_null),
+            on_boolean: None,
+            on_int: None,
+            on_long: Some(on_long),
+            on_float: None,
+            on_double: None,
+            on_bytes: None,
+            on_string: Some(on_string),
+            on_fixed: None,
+            on_enum: Some(on_enum),
+            on_union: Some(on_union),
+            on_array_start: Some(on_start),
+            on_array_end: Some(on_end),
+            on_map_start: None,
+            on_map_key: None,
+            on_map_end: None,
+            on_record_start: Some(on_start),
+            on_field: Some(on_field),
+            on_record_end: Some(on_end),
+        }
+    }
+
+    fn visit_both(schema: &Schema, value: Value) -> (c_int, Vec<String>) {
+        let mut value_events = Vec::new();
+        let code = visit_value(&visitor(&mut value_events), &value).unwrap();
+
+        let datum = to_avro_datum(schema, value).unwrap();
+        let mut datum_events = Vec::new();
+        let datum_code =
+            visit_encoded(&visitor(&mut datum_events), schema, &mut &datum[..]).unwrap();
+
+        assert_eq!(code, datum_code);
+        assert_eq!(value_events, datum_events);
+        (code, value_events)
+    }
+
+    #[test]
+    fn visit_record() {
+        let schema = Schema::parse_str(r#"
+        {"type": "record", "name": "Test", "fields": [
+            {"name": "ids", "type": {"type": "array", "items": "long"}},
+            {"name": "comment", "type": ["null", "string"]},
+            {"name": "suit", "type": {"type": "enum", "name": "Suit", "symbols": ["spades", "hearts"]}}
+        ]}
+        "#).unwrap();
+        let record = |ids: Vec<i64>| {
+            Value::Record(vec![
+                (
+                    "ids".to_owned(),
+                    Value::Array(ids.into_iter().map(Value::Long).collect()),
+                ),
+                ("comment".to_owned(), Value::Union(None)),
+                ("suit".to_owned(), Value::Enum(1, "hearts".to_owned())),
+            ])
+        };
+
+        let (code, events) = visit_both(&schema, record(vec![1, 2]));
+        assert_eq!(code, 0);
+        assert_eq!(
+            events,
+            vec![
+                "start",
+                "field ids",
+                "start",
+                "long 1",
+                "long 2",
+                "end",
+                "field comment",
+                "union 0",
+                "null",
+                "field suit",
+                "enum 1 hearts",
+                "end",
+            ]
+        );
+
+        let (code, events) = visit_both(&schema, record(vec![1, -2, 3]));
+        assert_eq!(code, -1);
+        assert_eq!(
+            events,
+            vec!["start", "field ids", "start", "long 1", "long -2"]
+        );
+    }
+
+    #[test]
+    fn visit_invalid_datum() {
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "string"}"#).unwrap();
+        let mut events = Vec::new();
+        let visitor = visitor(&mut events);
+        assert!(visit_encoded(&visitor, &schema, &mut &[2, 2, 0xff, 0][..]).is_err());
+        assert!(visit_encoded(&visitor, &schema, &mut &[2][..]).is_err());
+
+        // a block of i64::MAX nulls, which take no bytes
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "null"}"#).unwrap();
+        let datum = [0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00];
+        assert!(visit_encoded(&visitor, &schema, &mut &datum[..]).is_err());
+    }
+}
@@ -1,16 +1,34 @@
-use avro_rs::to_avro_datum;
 use avro_rs::schema::Schema;
+use avro_rs::to_avro_datum;
 use avro_rs::types::Value;
-use avro_rs::Writer;
-use avro_utils::avro_value_from_pickle;
+use avro_utils::{
+    avro_value_from_pickle_with, avro_values_from_pickle, conform_value, conform_value_ref,
+};
 use codec::AvroCodec;
+use container::Writer;
 use core::AvroByteArray;
+use encode::{encoded_size, to_avro_datum_ref};
+use failure::err_msg;
+use options::{conversion_options, AvroConversionOptions, ConversionOptions};
+use std::slice;
 use schema::AvroSchema;
 use serde_pickle;
 use types::AvroValue;
 
 pub struct AvroWriter;
 
+/// An avro writer, along with the options to convert the pickled values appended to it.
+pub struct WriterHandle<'a> {
+    pub writer: Writer<'a, Vec<u8>>,
+    pub options: ConversionOptions,
+}
+
+impl<'a> WriterHandle<'a> {
+    fn boxed(writer: Writer<'a, Vec<u8>>, options: ConversionOptions) -> *mut AvroWriter {
+        Box::into_raw(Box::new(WriterHandle { writer, options })) as *mut AvroWriter
+    }
+}
+
 ffi_fn! {
     /// Create an avro writer given an avro schema, an avro byte array used as buffer and an avro codec.
     unsafe fn avro_writer_new(
@@ -20,46 +38,140 @@ ffi_fn! {
         let schema = &*(schema as *const Schema);
         let codec = codec.to_codec();
         let writer = Writer::with_codec(schema, Vec::new(), codec);
-        Ok(Box::into_raw(Box::new(writer)) as *mut AvroWriter)
+        Ok(WriterHandle::boxed(writer, ConversionOptions::default()))
+    }
+}
+
+ffi_fn! {
+    /// Create an avro writer given an avro schema, an avro codec and the options to convert the
+    /// pickled values appended with `avro_writer_append`. Default options are used if `options`
+    /// is null.
+    unsafe fn avro_writer_new_with_options(
+        schema: *const AvroSchema,
+        codec: AvroCodec,
+        options: *const AvroConversionOptions
+    ) -> Result<*mut AvroWriter> {
+        let schema = &*(schema as *const Schema);
+        let codec = codec.to_codec();
+        let writer = Writer::with_codec(schema, Vec::new(), codec);
+        Ok(WriterHandle::boxed(writer, conversion_options(options)?))
+    }
+}
+
+ffi_fn! {
+    /// Create an avro writer whose output only depends on the values appended to it: map
+    /// entries are written sorted by key, and the sync marker is derived from the schema.
+    unsafe fn avro_writer_new_deterministic(
+        schema: *const AvroSchema,
+        codec: AvroCodec
+    ) -> Result<*mut AvroWriter> {
+        let schema = &*(schema as *const Schema);
+        let codec = codec.to_codec();
+        let writer = Writer::deterministic(schema, Vec::new(), codec)?;
+        Ok(WriterHandle::boxed(writer, ConversionOptions::default()))
     }
 }
 
 ffi_fn! {
     /// Append a pickled avro value to an avro writer. Writing is not necessarily happening here.
     /// Call `avro_writer_flush` to force an actual write.
+    ///
+    /// If the value does not match the schema of the writer, the error message gives the path of
+    /// the offending value, the expected schema and the Python type found.
     unsafe fn avro_writer_append(
         writer: *mut AvroWriter,
         value: *const AvroByteArray
     ) -> Result<usize> {
-        let writer = &mut *(writer as *mut Writer<Vec<u8>>);
+        let WriterHandle { writer, options } = &mut *(writer as *mut WriterHandle);
         let pickle = serde_pickle::from_slice((&*value).as_slice());
-        let value = avro_value_from_pickle(writer.schema(), pickle?);
-        Ok(writer.append(value?)?)
+        let value = avro_value_from_pickle_with(writer.schema(), pickle?, options);
+        writer.append_value_ref(&value?)
+    }
+}
+
+ffi_fn! {
+    /// Append a pickled list of avro values to an avro writer, returning the number of values
+    /// appended. Writing is not necessarily happening here. Call `avro_writer_flush` to force an
+    /// actual write.
+    ///
+    /// If `errors` is null, no value is appended unless all of them can be. Otherwise the values
+    /// which cannot be appended are skipped, and `errors` is set to a pickled list of
+    /// `(index, message)` tuples for them, to be freed with `avro_byte_array_free`. The byte array
+    /// `errors` points to is overwritten without being freed, so it must not hold one still to be
+    /// freed, such as the errors of a previous call.
+    unsafe fn avro_writer_append_batch(
+        writer: *mut AvroWriter,
+        values: *const AvroByteArray,
+        errors: *mut AvroByteArray
+    ) -> Result<usize> {
+        let WriterHandle { writer, options } = &mut *(writer as *mut WriterHandle);
+        let pickle = serde_pickle::from_slice((&*values).as_slice())?;
+        let values = avro_values_from_pickle(writer.schema(), pickle, options)?;
+
+        if errors.is_null() {
+            let values = values.into_iter().collect::<Result<Vec<_>, _>>()?;
+            let schema = writer.schema();
+            if let Some(index) = values.iter().position(|value| !value.validate(schema)) {
+                return Err(err_msg(format!(
+                    "value at index {} does not match the schema of the writer",
+                    index
+                )))
+            }
+            for value in &values {
+                writer.append_value_ref(value)?;
+            }
+            return Ok(values.len())
+        }
+
+        let mut appended = 0;
+        let mut failures = Vec::new();
+        for (index, value) in values.into_iter().enumerate() {
+            match value.and_then(|value| writer.append_value_ref(&value)) {
+                Ok(_) => appended += 1,
+                Err(err) => failures.push((index, err.to_string())),
+            }
+        }
+        let failures = serde_pickle::to_vec(&failures, options.use_proto_3()?)?;
+        *errors = AvroByteArray::from_vec_u8(failures);
+        Ok(appended)
     }
 }
 
 ffi_fn! {
     unsafe fn avro_writer_append2(writer: *mut AvroWriter, value: *mut AvroValue) -> Result<usize> {
-        let writer = &mut *(writer as *mut Writer<Vec<u8>>);
+        let writer = &mut (*(writer as *mut WriterHandle)).writer;
         let value = *(Box::from_raw(value as *mut Value));
-        let value = value.resolve(writer.schema())?;  // Python's type system is not as strict as Rust's. This `.resolve` allows us to be more laxist
-        Ok(writer.append_value_ref(&value)?)
+        let value = conform_value(writer.schema(), value)?;  // Python's type system is not as strict as Rust's. Resolving invalid values allows us to be more laxist
+        writer.append_value_ref(&value)
+    }
+}
+
+ffi_fn! {
+    /// Append an avro value to an avro writer, without taking ownership of the value. Writing is
+    /// not necessarily happening here. Call `avro_writer_flush` to force an actual write.
+    unsafe fn avro_writer_append_ref(
+        writer: *mut AvroWriter,
+        value: *const AvroValue
+    ) -> Result<usize> {
+        let writer = &mut (*(writer as *mut WriterHandle)).writer;
+        let value = conform_value_ref(writer.schema(), &*(value as *const Value))?;
+        writer.append_value_ref(&value)
     }
 }
 
 ffi_fn! {
     /// Flush an avro writer.
     unsafe fn avro_writer_flush(writer: *mut AvroWriter) -> Result<usize> {
-        let writer = &mut *(writer as *mut Writer<Vec<u8>>);
-        Ok(writer.flush()?)
+        let writer = &mut (*(writer as *mut WriterHandle)).writer;
+        writer.flush()
     }
 }
 
 ffi_fn! {
     /// Consume an avro writer and return the avro serialized data.
     unsafe fn avro_writer_into_data(writer: *mut AvroWriter) -> Result<AvroByteArray> {
-        let writer = Box::from_raw(writer as *mut Writer<Vec<u8>>);
-        let buf = (*writer).into_inner();
+        let handle = Box::from_raw(writer as *mut WriterHandle);
+        let buf = handle.writer.into_inner();
         Ok(AvroByteArray::from_vec_u8(buf))
     }
 }
@@ -69,8 +181,74 @@ ffi_fn! {
     unsafe fn avro_to_avro_datum(schema: *const AvroSchema, value: *mut AvroValue) -> Result<AvroByteArray> {
         let schema = &*(schema as *const Schema);
         let value = *(Box::from_raw(value as *mut Value));
-        let value = value.resolve(schema)?;
+        let value = conform_value(schema, value)?;
         let buf = to_avro_datum(schema, value)?;
         Ok(AvroByteArray::from_vec_u8(buf))
     }
 }
+
+ffi_fn! {
+    /// Write a single avro datum to a buffer and return the avro serialized data, without taking
+    /// ownership of the value. The bytes are the same as the ones of `avro_to_avro_datum`.
+    unsafe fn avro_to_avro_datum_ref(
+        schema: *const AvroSchema,
+        value: *const AvroValue
+    ) -> Result<AvroByteArray> {
+        let schema = &*(schema as *const Schema);
+        let value = conform_value_ref(schema, &*(value as *const Value))?;
+        let buf = to_avro_datum_ref(schema, &value, false)?;
+        Ok(AvroByteArray::from_vec_u8(buf))
+    }
+}
+
+ffi_fn! {
+    /// Write a single avro datum to a buffer and return the avro serialized data, with map
+    /// entries sorted by key so that the same value always gives the same bytes. The value is
+    /// not consumed.
+    unsafe fn avro_to_avro_datum_deterministic(
+        schema: *const AvroSchema,
+        value: *const AvroValue
+    ) -> Result<AvroByteArray> {
+        let schema = &*(schema as *const Schema);
+        let value = conform_value_ref(schema, &*(value as *const Value))?;
+        let buf = to_avro_datum_ref(schema, &value, true)?;
+        Ok(AvroByteArray::from_vec_u8(buf))
+    }
+}
+
+ffi_fn! {
+    /// Number of bytes `avro_to_avro_datum` would produce for a value, without encoding it.
+    unsafe fn avro_value_encoded_size(
+        schema: *const AvroSchema,
+        value: *const AvroValue
+    ) -> Result<usize> {
+        let schema = &*(schema as *const Schema);
+        let value = conform_value_ref(schema, &*(value as *const Value))?;
+        Ok(encoded_size(&value, schema))
+    }
+}
+
+ffi_fn! {
+    /// Number of bytes `avro_to_avro_datum` would produce for each of `n` values, written to
+    /// `sizes`. Return the total number of bytes.
+    unsafe fn avro_value_encoded_sizes(
+        schema: *const AvroSchema,
+        values: *const *const AvroValue,
+        n: usize,
+        sizes: *mut usize
+    ) -> Result<usize> {
+        let schema = &*(schema as *const Schema);
+        if n == 0 {
+            return Ok(0)
+        }
+        let values = slice::from_raw_parts(values, n);
+        let sizes = slice::from_raw_parts_mut(sizes, n);
+        let mut total = 0;
+        for (value, size) in values.iter().zip(sizes.iter_mut()) {
+            let value = conform_value_ref(schema, &*(*value as *const Value))?;
+            *size = encoded_size(&value, schema);
+            total += *size;
+        }
+        Ok(total)
+    }
+}